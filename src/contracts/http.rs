@@ -12,6 +12,7 @@ pub struct PostOptions {
 
 #[derive(Debug)]
 pub struct PostResponse {
+  pub status: u16,
   pub body: Bytes,
 }
 
@@ -24,6 +25,7 @@ pub struct GetOptions {
 
 #[derive(Debug)]
 pub struct GetResponse {
+  pub status: u16,
   pub headers: HeaderMap,
   pub body: Bytes,
 }
@@ -1,8 +1,44 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
+/// What a text to speech provider supports, so callers don't ask for features it doesn't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TtsCapabilities {
+  /// Whether the text may be written in SSML.
+  pub ssml: bool,
+  /// Whether the provider reports when each word is spoken.
+  pub speech_marks: bool,
+  /// Whether the speaking rate can be changed.
+  pub rate_control: bool,
+  /// The formats of the audio files, like "mp3".
+  pub formats: Vec<&'static str>,
+  /// The maximum length of the text, `None` when there's no limit.
+  pub max_input_len: Option<usize>,
+  /// The sample rates, in Hz, the audio can be requested in. Empty when the
+  /// provider decides the sample rate.
+  pub sample_rates: Vec<u32>,
+}
+
+impl Default for TtsCapabilities {
+  /// Assumes only plain text and mp3 files are supported.
+  fn default() -> Self {
+    Self {
+      ssml: false,
+      speech_marks: false,
+      rate_control: false,
+      formats: vec!["mp3"],
+      max_input_len: None,
+      sample_rates: Vec::new(),
+    }
+  }
+}
+
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait TextToSpeech: Send + Sync {
   async fn create_audio(&self, text: String) -> Result<Vec<String>>;
+
+  fn capabilities(&self) -> TtsCapabilities {
+    TtsCapabilities::default()
+  }
 }
@@ -40,9 +40,13 @@ impl contracts::http::HttpClient for ReqwestHttpClient {
       }
     }
 
-    let body = request_builder.body(body).send().await?.bytes().await?;
+    let response = request_builder.body(body).send().await?;
+    let status = response.status().as_u16();
 
-    Ok(PostResponse { body })
+    Ok(PostResponse {
+      status,
+      body: response.bytes().await?,
+    })
   }
 
   async fn get(&self, url: &str, options: Option<GetOptions>) -> Result<GetResponse> {
@@ -65,10 +69,12 @@ impl contracts::http::HttpClient for ReqwestHttpClient {
     }
 
     let response = request_builder.send().await?;
+    let status = response.status().as_u16();
     let headers = response.headers().clone();
 
     Ok(GetResponse {
       body: response.bytes().await?,
+      status,
       headers,
     })
   }
@@ -27,7 +27,7 @@ mod video_stream_api;
 
 use text_generation::TextGenerator;
 use translation::Translation;
-use tts::Tts;
+use tts::{QueuedTts, Tts};
 use video::Video;
 
 use crate::{
@@ -236,7 +236,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   )
   .event_handler(Bot::new(
     ChatBot::new(
-      Arc::new(Tts::new()),
+      Arc::new(QueuedTts::new(
+        Arc::new(Tts::new(
+          tts::Config::default(),
+          Arc::new(ReqwestHttpClient::new()),
+          None,
+        )),
+        tts::QueueConfig::default(),
+      )),
       TextGenerator::new(
         Config {
           chaiml_developer_uuid: env_key("CHAIML_DEVELOPER_UUID")?,
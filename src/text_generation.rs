@@ -145,6 +145,7 @@ mod generate_tests {
 
       http_client.expect_post().returning(move |_, _, _| {
         Ok(PostResponse {
+          status: 200,
           body: Bytes::from(serde_json::to_string(&serde_json::json!({
             "data": input
           }))?),
@@ -0,0 +1,142 @@
+//! Decides how words written in capital letters, like "FBI" and "NASA", are read.
+
+use std::collections::HashMap;
+
+use super::numbers;
+
+/// How a word written in capital letters is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcronymReading {
+  /// Letter by letter: "FBI" as "F-B-I".
+  Spell,
+  /// As a word: "NASA" as "nasa".
+  Word,
+}
+
+/// Longer words are never spelled, they're usually someone shouting.
+const MAX_SPELLED_LEN: usize = 5;
+
+const VOWELS: &str = "AEIOU";
+
+/// Pairs of consonants that can start a syllable in portuguese, like the "BR" in "BRASIL".
+const CLUSTERS: [&str; 16] = [
+  "BL", "BR", "CL", "CR", "DR", "FL", "FR", "GL", "GR", "PL", "PR", "TR", "VR", "CH", "LH", "NH",
+];
+
+/// Guesses how a word written in capital letters is read: words without vowels or
+/// with consonants that can't be pronounced together are spelled.
+pub fn guess_reading(word: &str) -> AcronymReading {
+  let letters: Vec<char> = word.chars().collect();
+  let is_vowel = |character: &char| VOWELS.contains(*character);
+
+  if !letters.iter().any(is_vowel) {
+    return AcronymReading::Spell;
+  }
+
+  let mut consonants = 0;
+
+  for (i, letter) in letters.iter().enumerate() {
+    if is_vowel(letter) {
+      consonants = 0;
+      continue;
+    }
+
+    consonants += 1;
+
+    if consonants >= 3 {
+      return AcronymReading::Spell;
+    }
+
+    // Two consonants before a vowel must start a syllable together.
+    if consonants == 2 && letters.get(i + 1).is_some_and(is_vowel) {
+      let pair: String = letters[i - 1..=i].iter().collect();
+      if !CLUSTERS.contains(&pair.as_str()) {
+        return AcronymReading::Spell;
+      }
+    }
+  }
+
+  AcronymReading::Word
+}
+
+/// Replaces the words of `text` written in capital letters by how they are read.
+/// Words in `overrides` are read the way they are mapped to instead of guessed.
+/// Words mixing cases, like "iPhone" and "eSports", are left as written.
+pub fn verbalize_acronyms(text: &str, overrides: &HashMap<String, AcronymReading>) -> String {
+  numbers::replace_words(text, |word| {
+    if word.len() < 2 || !word.chars().all(|character| character.is_ascii_uppercase()) {
+      return None;
+    }
+
+    let reading = match overrides.get(word) {
+      Some(reading) => *reading,
+      None => {
+        // Laughter like "KKKK" is not spelled.
+        let repeated = word.chars().all(|character| word.starts_with(character));
+
+        if word.len() > MAX_SPELLED_LEN || repeated {
+          AcronymReading::Word
+        } else {
+          guess_reading(word)
+        }
+      }
+    };
+
+    Some(match reading {
+      AcronymReading::Spell => word.chars().map(String::from).collect::<Vec<_>>().join("-"),
+      AcronymReading::Word => word.to_lowercase(),
+    })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_guess_reading() {
+    let tests = vec![
+      ("FBI", AcronymReading::Spell),
+      ("CPF", AcronymReading::Spell),
+      ("IBGE", AcronymReading::Spell),
+      ("UFRJ", AcronymReading::Spell),
+      ("NASA", AcronymReading::Word),
+      ("ONU", AcronymReading::Word),
+      ("PIX", AcronymReading::Word),
+      ("BRASIL", AcronymReading::Word),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, guess_reading(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn test_verbalize_acronyms() {
+    let overrides = HashMap::from([
+      (String::from("USP"), AcronymReading::Word),
+      (String::from("ONU"), AcronymReading::Spell),
+    ]);
+
+    let tests = vec![
+      ("", ""),
+      ("o FBI chegou", "o F-B-I chegou"),
+      ("a NASA lançou", "a nasa lançou"),
+      ("a ONU e a USP", "a O-N-U e a usp"),
+      ("KKKKKK", "kkkkkk"),
+      ("QUE LIVE BOA", "que live boa"),
+      ("(CPF)", "(C-P-F)"),
+      ("a Nasa, o É", "a Nasa, o É"),
+      ("um iPhone, eSports e iOS", "um iPhone, eSports e iOS"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        verbalize_acronyms(input, &overrides),
+        "input={}",
+        input
+      );
+    }
+  }
+}
@@ -0,0 +1,144 @@
+//! How long to wait before retrying a request that failed.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+/// How randomness is added to the delays so retries that failed at the
+/// same time don't all hit the api at the same time again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jitter {
+  /// Waits exactly the exponential delay.
+  None,
+  /// Waits a random delay between zero and the exponential delay.
+  #[default]
+  Full,
+  /// Waits half of the exponential delay plus a random delay up to the other half.
+  Equal,
+  /// Waits a random delay between `initial` and three times the previous delay.
+  Decorrelated,
+}
+
+/// Exponential backoff: the delay doubles after each attempt until it reaches `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+  /// The delay before the first retry.
+  pub initial: Duration,
+  /// The delay never grows past this.
+  pub max: Duration,
+  pub jitter: Jitter,
+}
+
+impl Backoff {
+  /// Returns the exponential delay, without jitter, before retrying after `attempt` failed.
+  /// `attempt` starts at 0.
+  pub fn delay(&self, attempt: u32) -> Duration {
+    let factor = 2_u32.saturating_pow(attempt);
+    self.initial.saturating_mul(factor).min(self.max)
+  }
+
+  /// Returns how long to wait before retrying after `attempt` failed.
+  /// `previous` is the delay waited before `attempt`, zero for the first attempt.
+  pub fn jittered_delay<R: Rng + ?Sized>(
+    &self,
+    attempt: u32,
+    previous: Duration,
+    rng: &mut R,
+  ) -> Duration {
+    let delay = self.delay(attempt);
+
+    let random_between = |rng: &mut R, low: Duration, high: Duration| {
+      let low = low.as_millis() as u64;
+      let high = (high.as_millis() as u64).max(low);
+      Duration::from_millis(rng.gen_range(low..=high))
+    };
+
+    match self.jitter {
+      Jitter::None => delay,
+      Jitter::Full => random_between(rng, Duration::ZERO, delay),
+      Jitter::Equal => delay / 2 + random_between(rng, Duration::ZERO, delay / 2),
+      Jitter::Decorrelated => {
+        random_between(rng, self.initial, previous.saturating_mul(3)).min(self.max)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::StdRng, SeedableRng};
+
+  use super::*;
+
+  fn backoff(jitter: Jitter) -> Backoff {
+    Backoff {
+      initial: Duration::from_millis(100),
+      max: Duration::from_secs(1),
+      jitter,
+    }
+  }
+
+  #[test]
+  fn test_delay() {
+    let backoff = backoff(Jitter::None);
+
+    let tests = vec![
+      (0, Duration::from_millis(100)),
+      (1, Duration::from_millis(200)),
+      (2, Duration::from_millis(400)),
+      (3, Duration::from_millis(800)),
+      (4, Duration::from_secs(1)),
+      (100, Duration::from_secs(1)),
+    ];
+
+    for (attempt, expected) in tests {
+      assert_eq!(expected, backoff.delay(attempt), "attempt={}", attempt);
+    }
+  }
+
+  #[test]
+  fn jittered_delays_stay_within_the_strategy_bounds() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    for jitter in [
+      Jitter::None,
+      Jitter::Full,
+      Jitter::Equal,
+      Jitter::Decorrelated,
+    ] {
+      let backoff = backoff(jitter);
+
+      let mut previous = Duration::ZERO;
+
+      for attempt in 0..10 {
+        let exponential = backoff.delay(attempt);
+        let delay = backoff.jittered_delay(attempt, previous, &mut rng);
+
+        let (low, high) = match jitter {
+          Jitter::None => (exponential, exponential),
+          Jitter::Full => (Duration::ZERO, exponential),
+          Jitter::Equal => (exponential / 2, exponential),
+          Jitter::Decorrelated => (
+            backoff.initial,
+            previous
+              .saturating_mul(3)
+              .max(backoff.initial)
+              .min(backoff.max),
+          ),
+        };
+
+        assert!(
+          low <= delay && delay <= high,
+          "jitter={:?} attempt={} delay={:?} low={:?} high={:?}",
+          jitter,
+          attempt,
+          delay,
+          low,
+          high
+        );
+
+        previous = delay;
+      }
+    }
+  }
+}
@@ -0,0 +1,110 @@
+//! Caching the urls of the audio files created for each piece of text, so messages
+//! that are sent often are not synthesized again.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use super::{CallOptions, ChunkDetails, Tts, Voice};
+
+/// How long the url of an audio file is cached for.
+const AUDIO_URL_CACHE_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+impl Tts {
+  /// Returns the audio file for `text`, using the cache when possible.
+  pub(super) async fn synthesize_chunk(
+    &self,
+    text: String,
+    voice: &Voice,
+    rate: Option<u32>,
+    options: &CallOptions,
+  ) -> Result<ChunkDetails> {
+    let cache = match &self.cache {
+      None => return self.generate_audio_with_fallbacks(text, voice, rate).await,
+      Some(cache) => cache,
+    };
+
+    let key = audio_url_cache_key(&text, voice, rate);
+
+    if !options.bypass_cache {
+      match cache.get(&key).await {
+        Err(err) => error!("error reading audio url from cache. error={:?}", err),
+        Ok(Some(url)) => {
+          info!("audio url cache hit");
+          return Ok(ChunkDetails {
+            url: String::from_utf8_lossy(&url).to_string(),
+            voice: voice.clone(),
+            attempts: 0,
+            pause_after: Duration::ZERO,
+            rate,
+          });
+        }
+        Ok(None) => {}
+      }
+    }
+
+    let chunk = self
+      .generate_audio_with_fallbacks(text, voice, rate)
+      .await?;
+
+    // Audio created by a fallback voice is not cached so the voice
+    // of the message is tried again next time.
+    let write_to_cache = !options.bypass_cache || options.refresh_cache;
+    if write_to_cache && &chunk.voice == voice {
+      let cache = Arc::clone(cache);
+      let value = chunk.url.as_bytes().to_vec();
+      self.spawn_background_task(async move {
+        if let Err(err) = cache.put(key, value, AUDIO_URL_CACHE_TTL).await {
+          error!("error writing audio url to cache. error={:?}", err);
+        }
+      });
+    }
+
+    Ok(chunk)
+  }
+}
+
+/// Returns the key the url of the audio is cached with. The text is hashed so messages
+/// written by viewers don't end up in the cache keys.
+pub(super) fn audio_url_cache_key(text: &str, voice: &Voice, rate: Option<u32>) -> Vec<u8> {
+  let text = format!("{:x}", Sha256::digest(text.as_bytes()));
+
+  match rate {
+    None => format!("tts:{}:{}:{}", voice.engine, voice.voice, text).into_bytes(),
+    Some(rate) => format!("tts:{}:{}@{}:{}", voice.engine, voice.voice, rate, text).into_bytes(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cache_keys_do_not_contain_the_text() {
+    let voice = Voice::default();
+
+    let key = String::from_utf8(audio_url_cache_key("meu cpf é 123", &voice, None)).unwrap();
+    assert!(!key.contains("cpf"));
+    assert!(!key.contains("123"));
+
+    let keys = [
+      audio_url_cache_key("olá", &voice, None),
+      audio_url_cache_key("olá!", &voice, None),
+      audio_url_cache_key("olá", &voice, Some(120)),
+      audio_url_cache_key(
+        "olá",
+        &Voice {
+          engine: voice.engine.clone(),
+          voice: String::from("en-US"),
+        },
+        None,
+      ),
+    ];
+    for (i, key) in keys.iter().enumerate() {
+      assert!(!keys[i + 1..].contains(key), "i={}", i);
+    }
+    assert_eq!(keys[0], audio_url_cache_key("olá", &voice, None));
+  }
+}
@@ -0,0 +1,576 @@
+//! Splits messages into pieces that are small enough to be sent to the tts api.
+
+use std::{fmt::Write, sync::Arc};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How the length of a piece of text is measured. Each tts api counts
+/// the length of the text it accepts in a different way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthUnit {
+  /// Number of bytes in the utf-8 encoded text.
+  Bytes,
+  /// Number of unicode scalar values, what a rust `char` is.
+  #[default]
+  Scalars,
+  /// Number of extended grapheme clusters, what a person would call a character.
+  Graphemes,
+}
+
+impl LengthUnit {
+  pub fn len(&self, text: &str) -> usize {
+    match self {
+      LengthUnit::Bytes => text.len(),
+      LengthUnit::Scalars => text.chars().count(),
+      LengthUnit::Graphemes => text.graphemes(true).count(),
+    }
+  }
+}
+
+/// Finds where a piece of text that doesn't fit in a chunk can be split.
+/// Splits between words separated by whitespace by default, scripts written
+/// without spaces need a finder that knows where their words end.
+#[derive(Clone)]
+pub struct BreakFinder(Arc<dyn Fn(&str) -> Vec<usize> + Send + Sync>);
+
+impl BreakFinder {
+  /// `find` returns the byte offsets where the text can be split. The text
+  /// before an offset goes to a chunk and the text after it to the next one.
+  pub fn new(find: impl Fn(&str) -> Vec<usize> + Send + Sync + 'static) -> Self {
+    Self(Arc::new(find))
+  }
+
+  /// Splits after the whitespace that follows each word.
+  pub fn whitespace() -> Self {
+    Self::new(|text| {
+      text
+        .char_indices()
+        .filter(|(_, character)| character.is_whitespace())
+        .map(|(i, character)| i + character.len_utf8())
+        .collect()
+    })
+  }
+
+  /// Splits `text` at the break points, ignoring offsets that are out of bounds
+  /// or in the middle of a character.
+  fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+    let mut offsets = (self.0)(text);
+    offsets.retain(|offset| *offset > 0 && *offset < text.len() && text.is_char_boundary(*offset));
+    offsets.sort_unstable();
+    offsets.dedup();
+
+    let mut pieces = Vec::with_capacity(offsets.len() + 1);
+    let mut start = 0;
+    for offset in offsets {
+      pieces.push(&text[start..offset]);
+      start = offset;
+    }
+    pieces.push(&text[start..]);
+
+    pieces
+  }
+}
+
+impl Default for BreakFinder {
+  fn default() -> Self {
+    Self::whitespace()
+  }
+}
+
+impl std::fmt::Debug for BreakFinder {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("BreakFinder")
+  }
+}
+
+/// Decides the order the chunks of a message are read in. Reads them in the order
+/// they were written by default.
+#[derive(Clone)]
+pub struct ChunkOrder(Arc<dyn Fn(&[String]) -> Vec<usize> + Send + Sync>);
+
+impl ChunkOrder {
+  /// `order` receives the chunks in the order they were written and returns
+  /// the indexes of the chunks in the order they should be read.
+  pub fn new(order: impl Fn(&[String]) -> Vec<usize> + Send + Sync + 'static) -> Self {
+    Self(Arc::new(order))
+  }
+
+  pub fn identity() -> Self {
+    Self::new(|chunks| (0..chunks.len()).collect())
+  }
+
+  /// Returns the indexes of `chunks` in the order they should be read. Falls back to the
+  /// order they were written when the function doesn't return every index exactly once.
+  pub fn order(&self, chunks: &[String]) -> Vec<usize> {
+    let order = (self.0)(chunks);
+
+    let mut sorted = order.clone();
+    sorted.sort_unstable();
+    if sorted != (0..chunks.len()).collect::<Vec<_>>() {
+      return (0..chunks.len()).collect();
+    }
+
+    order
+  }
+}
+
+impl Default for ChunkOrder {
+  fn default() -> Self {
+    Self::identity()
+  }
+}
+
+impl std::fmt::Debug for ChunkOrder {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("ChunkOrder")
+  }
+}
+
+pub fn split_str_and_include_separator(text: &str) -> Vec<(Option<char>, String)> {
+  let mut pieces = vec![];
+
+  let mut buffer = String::new();
+
+  for character in text.chars() {
+    if character == '.' {
+      pieces.push((Some('.'), std::mem::take(&mut buffer)));
+    } else if character == ',' {
+      pieces.push((Some(','), std::mem::take(&mut buffer)));
+    } else {
+      buffer.push(character);
+    }
+  }
+
+  if !buffer.is_empty() {
+    pieces.push((None, std::mem::take(&mut buffer)));
+  }
+
+  pieces
+}
+
+/// The tts api accepts only `max_chunk_len` characters at a time, so if we get a text thats longer than that
+/// we split the text using the punctuation. Pieces between punctuation that are still
+/// too long are split at the break points found by `break_finder`.
+/// A text of exactly `max_chunk_len` fits in a single chunk.
+pub fn divide_text_into_chunks(
+  text: &str,
+  max_chunk_len: usize,
+  length_unit: LengthUnit,
+  break_finder: &BreakFinder,
+) -> Result<Vec<String>, std::fmt::Error> {
+  let mut chunks = vec![];
+
+  let mut buffer = String::new();
+
+  for (separator, piece) in split_str_and_include_separator(text) {
+    // Spaces before punctuation are dropped: "olá , pessoal" becomes "olá, pessoal".
+    let piece = match separator {
+      None => piece.as_str(),
+      Some(_) => piece.trim_end(),
+    };
+
+    let words = if length_unit.len(piece) > max_chunk_len {
+      break_finder.split(piece)
+    } else {
+      vec![piece]
+    };
+
+    for word in words {
+      if !buffer.is_empty() && length_unit.len(&buffer) + length_unit.len(word) > max_chunk_len {
+        push_chunk(&mut chunks, &mut buffer);
+      }
+
+      buffer.push_str(word);
+    }
+
+    if let Some(separator) = separator {
+      match buffer.chars().last() {
+        // Punctuation at the start of a chunk is not read.
+        None => {}
+        // Different marks next to each other, like "olá.,", become a single mark.
+        // Repeated marks, like "...", are kept.
+        Some(last) if piece.is_empty() && is_separator(last) && last != separator => {
+          if separator == '.' {
+            buffer.pop();
+            buffer.push('.');
+          }
+        }
+        Some(_) => write!(&mut buffer, "{}", separator)?,
+      }
+    }
+  }
+
+  push_chunk(&mut chunks, &mut buffer);
+
+  Ok(chunks)
+}
+
+/// Joins the last chunk with the one before it when it is shorter than `min_chunk_len`,
+/// so messages don't end with a tiny clip like "ok.". The joined chunk may be up to
+/// `tolerance` longer than `max_chunk_len`, otherwise the chunks are kept apart.
+pub fn merge_short_last_chunk(
+  chunks: &mut Vec<String>,
+  min_chunk_len: usize,
+  max_chunk_len: usize,
+  tolerance: usize,
+  length_unit: LengthUnit,
+) {
+  if chunks.len() < 2 {
+    return;
+  }
+
+  let last = &chunks[chunks.len() - 1];
+  if length_unit.len(last) >= min_chunk_len {
+    return;
+  }
+
+  let previous = &chunks[chunks.len() - 2];
+  let joined_len = length_unit.len(previous) + length_unit.len(" ") + length_unit.len(last);
+  if joined_len > max_chunk_len + tolerance {
+    return;
+  }
+
+  // SAFETY: there are at least 2 chunks.
+  let last = chunks.pop().unwrap();
+  let previous = chunks.last_mut().unwrap();
+  previous.push(' ');
+  previous.push_str(&last);
+}
+
+/// Splits `text` into sentences, each ending at a run of ".", "!" or "?" followed
+/// by whitespace. The text after the last mark is a sentence too.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+  let mut sentences = vec![];
+
+  let mut start = 0;
+  let mut characters = text.char_indices().peekable();
+
+  while let Some((i, character)) = characters.next() {
+    if !".!?".contains(character) {
+      continue;
+    }
+
+    let ends_sentence = match characters.peek() {
+      None => true,
+      Some((_, next)) => next.is_whitespace(),
+    };
+
+    if ends_sentence {
+      let end = i + character.len_utf8();
+      let sentence = text[start..end].trim();
+      if !sentence.is_empty() {
+        sentences.push(sentence);
+      }
+      start = end;
+    }
+  }
+
+  let rest = text[start..].trim();
+  if !rest.is_empty() {
+    sentences.push(rest);
+  }
+
+  sentences
+}
+
+fn is_separator(character: char) -> bool {
+  character == '.' || character == ','
+}
+
+/// Adds `buffer` to the chunks without the whitespace around it and clears it.
+fn push_chunk(chunks: &mut Vec<String>, buffer: &mut String) {
+  let chunk = buffer.trim();
+  if !chunk.is_empty() {
+    chunks.push(chunk.to_string());
+  }
+  buffer.clear();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_split_str_and_include_separator() {
+    let input = "Once upon a time, in a far away swamp, there lived an ogre named Shrek (Mike Myers) whose precious solitude is suddenly shattered by an invasion of annoying fairy tale characters.";
+    let expected = vec![
+      (
+          Some(
+              ',',
+          ),
+          String::from("Once upon a time"),
+      ),
+      (
+          Some(
+              ',',
+          ),
+          String::from(" in a far away swamp"),
+      ),
+      (
+          Some(
+              '.',
+          ),
+          String::from(" there lived an ogre named Shrek (Mike Myers) whose precious solitude is suddenly shattered by an invasion of annoying fairy tale characters"),
+      ),
+    ];
+    assert_eq!(expected, split_str_and_include_separator(input));
+  }
+
+  #[test]
+  fn test_divide_text_into_chunks() {
+    let tests = vec![
+    //   (
+    //   r#"
+    //   Once upon a time, in a far away swamp, there lived an ogre named Shrek (Mike Myers) whose precious solitude is suddenly shattered by an invasion of annoying fairy tale characters.
+    //   They were all banished from their kingdom by the evil Lord Farquaad (John Lithgow).
+    //   Determined to save their home -- not to mention his -- Shrek cuts a deal with Farquaad and sets out to rescue Princess Fiona (Cameron Diaz) to be Farquaad's bride.
+    //   Rescuing the Princess may be small compared to her deep, dark secret.
+    // "#,
+    // vec![
+    //   "\n      Once upon a time, in a far away swamp, there lived an ogre named Shrek (Mike Myers) whose precious solitude is suddenly shattered by an invasion of annoying fairy tale characters.",
+    //   "\n      They were all banished from their kingdom by the evil Lord Farquaad (John Lithgow).",
+    //   "\n      Determined to save their home -- not to mention his -- Shrek cuts a deal with Farquaad and sets out to rescue Princess Fiona (Cameron Diaz) to be Farquaad's bride.",
+    //   "\n      Rescuing the Princess may be small compared to her deep, dark secret.\n    ",
+    // ]
+    // ),
+    // (
+    //   "",
+    //   vec![]
+    // ),
+    // (
+    //   "Once upon. a time in. a far away swamp. there lived an ogre. named Shrek. ",
+    //   vec!["Once upon. a time in. a far away swamp. there lived an ogre. named Shrek. "]
+    // )
+    (
+      "Hmm... bem, eu definitivamente poderia fazer isso para você. Quer que eu faça um pequeno teste de sabor primeiro?",
+      vec!["Hmm... bem, eu definitivamente poderia fazer isso para você. Quer que eu faça um pequeno teste de sabor primeiro?"]
+    )
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        divide_text_into_chunks(input, 200, LengthUnit::Scalars, &BreakFinder::default()).unwrap()
+      );
+    }
+  }
+
+  #[test]
+  fn chunk_boundaries_depend_on_the_length_unit() {
+    // "👍🏽" is 1 grapheme made of 2 scalar values encoded in 8 bytes.
+    let input = "a👍🏽,b👍🏽,c👍🏽.";
+
+    let tests = vec![
+      (LengthUnit::Graphemes, vec!["a👍🏽,b👍🏽,c👍🏽."]),
+      (LengthUnit::Scalars, vec!["a👍🏽,b👍🏽,", "c👍🏽."]),
+      (LengthUnit::Bytes, vec!["a👍🏽,", "b👍🏽,", "c👍🏽."]),
+    ];
+
+    for (length_unit, expected) in tests {
+      assert_eq!(
+        expected,
+        divide_text_into_chunks(input, 8, length_unit, &BreakFinder::default()).unwrap(),
+        "length_unit={:?}",
+        length_unit
+      );
+    }
+  }
+
+  #[test]
+  fn long_pieces_are_split_at_break_points() {
+    let tests = vec![
+      (
+        BreakFinder::default(),
+        "olá pessoal tudo bem",
+        vec!["olá", "pessoal", "tudo", "bem"],
+      ),
+      // Without break points the piece is not split.
+      (
+        BreakFinder::default(),
+        "obrigadaaaaaa",
+        vec!["obrigadaaaaaa"],
+      ),
+      (
+        BreakFinder::new(|text| {
+          text
+            .match_indices('|')
+            .map(|(i, delimiter)| i + delimiter.len())
+            .collect()
+        }),
+        "一二三|四五六|七八九",
+        vec!["一二三|", "四五六|", "七八九"],
+      ),
+      // Offsets out of bounds or inside a character are ignored.
+      (
+        BreakFinder::new(|_| vec![0, 1, 3, 100]),
+        "一二三四五六",
+        vec!["一", "二三四五六"],
+      ),
+    ];
+
+    for (break_finder, input, expected) in tests {
+      assert_eq!(
+        expected,
+        divide_text_into_chunks(input, 5, LengthUnit::Scalars, &break_finder).unwrap(),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn text_at_the_limit_fits_in_a_single_chunk() {
+    let limit = 200;
+
+    let words = |len: usize| {
+      let mut text = "abcd ".repeat(len / 5 + 1);
+      text.truncate(len);
+      text.replace_range(len - 1.., "x");
+      text
+    };
+
+    for len in [limit - 1, limit] {
+      let text = words(len);
+      assert_eq!(len, text.len());
+      assert_eq!(
+        vec![text.clone()],
+        divide_text_into_chunks(&text, limit, LengthUnit::Scalars, &BreakFinder::default())
+          .unwrap(),
+        "len={}",
+        len
+      );
+    }
+
+    let text = words(limit + 1);
+    let chunks =
+      divide_text_into_chunks(&text, limit, LengthUnit::Scalars, &BreakFinder::default()).unwrap();
+    assert_eq!(2, chunks.len());
+    assert!(chunks.iter().all(|chunk| chunk.len() <= limit));
+
+    // The same with punctuation: "a," repeated.
+    for (len, expected_chunks) in [(limit - 1, 1), (limit, 1), (limit + 1, 2)] {
+      let text: String = "a,".repeat(len / 2 + 1).chars().take(len).collect();
+      assert_eq!(
+        expected_chunks,
+        divide_text_into_chunks(&text, limit, LengthUnit::Scalars, &BreakFinder::default())
+          .unwrap()
+          .len(),
+        "len={}",
+        len
+      );
+    }
+  }
+
+  #[test]
+  fn spacing_around_punctuation_is_natural() {
+    let tests = vec![
+      ("olá , pessoal .", vec!["olá, pessoal."]),
+      (
+        "olá pessoal.   tudo bem?",
+        vec!["olá pessoal.", "tudo bem?"],
+      ),
+      ("obrigado ., valeu", vec!["obrigado. valeu"]),
+      ("obrigado,. valeu", vec!["obrigado. valeu"]),
+      ("então... tá", vec!["então... tá"]),
+      (". , olá", vec!["olá"]),
+      ("   ", vec![]),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        divide_text_into_chunks(input, 15, LengthUnit::Scalars, &BreakFinder::default()).unwrap(),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_merge_short_last_chunk() {
+    let tests = vec![
+      (
+        vec!["muito obrigado pela doação.", "ok."],
+        vec!["muito obrigado pela doação. ok."],
+      ),
+      // Too long after joining, even with the tolerance.
+      (
+        vec!["muito obrigado pela doação e pelo apoio.", "ok."],
+        vec!["muito obrigado pela doação e pelo apoio.", "ok."],
+      ),
+      // Long enough.
+      (
+        vec!["muito obrigado.", "tamo junto."],
+        vec!["muito obrigado.", "tamo junto."],
+      ),
+      (vec!["ok."], vec!["ok."]),
+    ];
+
+    for (input, expected) in tests {
+      let mut chunks: Vec<String> = input.iter().map(|chunk| chunk.to_string()).collect();
+      merge_short_last_chunk(&mut chunks, 5, 30, 5, LengthUnit::Scalars);
+      assert_eq!(expected, chunks, "input={:?}", input);
+    }
+  }
+
+  #[test]
+  fn test_chunk_order() {
+    let chunks: Vec<String> = ["a", "b", "c"].into_iter().map(String::from).collect();
+
+    let tests = vec![
+      (ChunkOrder::default(), vec![0, 1, 2]),
+      (
+        ChunkOrder::new(|chunks| (0..chunks.len()).rev().collect()),
+        vec![2, 1, 0],
+      ),
+      // Not every chunk is read.
+      (ChunkOrder::new(|_| vec![2, 0]), vec![0, 1, 2]),
+      // A chunk is read twice.
+      (ChunkOrder::new(|_| vec![0, 0, 1]), vec![0, 1, 2]),
+    ];
+
+    for (order, expected) in tests {
+      assert_eq!(expected, order.order(&chunks));
+    }
+  }
+
+  #[test]
+  fn test_length_unit_len() {
+    let tests = vec![
+      ("", (0, 0, 0)),
+      ("abc", (3, 3, 3)),
+      ("ação", (6, 4, 4)),
+      ("👍🏽", (8, 2, 1)),
+    ];
+
+    for (input, (bytes, scalars, graphemes)) in tests {
+      assert_eq!(bytes, LengthUnit::Bytes.len(input), "input={}", input);
+      assert_eq!(scalars, LengthUnit::Scalars.len(input), "input={}", input);
+      assert_eq!(
+        graphemes,
+        LengthUnit::Graphemes.len(input),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_split_sentences() {
+    let tests = vec![
+      ("", vec![]),
+      ("olá", vec!["olá"]),
+      ("olá. tudo bem? sim!", vec!["olá.", "tudo bem?", "sim!"]),
+      ("então... ok?! beleza", vec!["então...", "ok?!", "beleza"]),
+      // Marks in the middle of a word don't end the sentence.
+      (
+        "acesse site.com.br agora.",
+        vec!["acesse site.com.br agora."],
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, split_sentences(input), "input={}", input);
+    }
+  }
+}
@@ -0,0 +1,91 @@
+//! The headers that authenticate requests to the tts api and how they are replaced
+//! when they expire.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+
+use super::{Tts, TtsError};
+
+/// Gets new credentials for the tts api when they expire, for apis that use
+/// temporary tokens. Returns the headers that authenticate the requests.
+#[derive(Clone)]
+pub struct CredentialsRefresher(
+  Arc<dyn Fn() -> BoxFuture<'static, Result<Vec<(String, String)>>> + Send + Sync>,
+);
+
+impl CredentialsRefresher {
+  pub fn new<F, Fut>(refresh: F) -> Self
+  where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<Vec<(String, String)>>> + Send + 'static,
+  {
+    Self(Arc::new(move || Box::pin(refresh())))
+  }
+}
+
+impl std::fmt::Debug for CredentialsRefresher {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("CredentialsRefresher")
+  }
+}
+
+/// The headers that authenticate requests to the tts api.
+pub(super) struct Credentials {
+  pub(super) headers: Vec<(String, String)>,
+  /// Incremented every time the credentials are refreshed.
+  pub(super) generation: u64,
+}
+
+impl Tts {
+  /// The headers sent with every request to the tts api.
+  pub(super) fn request_headers(&self) -> Vec<(String, String)> {
+    let mut headers = soundoftext_headers();
+    headers.extend(self.credentials.lock().unwrap().headers.iter().cloned());
+    headers
+  }
+
+  /// Replaces the credentials with the ones returned by `refresher`, unless they were
+  /// refreshed since `generation` by another request that found them expired.
+  pub(super) async fn refresh_credentials(
+    &self,
+    refresher: &CredentialsRefresher,
+    generation: u64,
+  ) -> Result<()> {
+    let _refreshing = self.refreshing.lock().await;
+
+    if self.credentials.lock().unwrap().generation != generation {
+      return Ok(());
+    }
+
+    let headers = (refresher.0)().await.context("refreshing credentials")?;
+
+    let mut credentials = self.credentials.lock().unwrap();
+    credentials.headers = headers;
+    credentials.generation += 1;
+
+    Ok(())
+  }
+}
+
+/// Fails when the response status means the credentials were not accepted.
+pub(super) fn check_auth_status(status: u16) -> Result<(), TtsError> {
+  match status {
+    401 => Err(TtsError::CredentialsExpired),
+    403 => Err(TtsError::InvalidCredentials),
+    _ => Ok(()),
+  }
+}
+
+fn soundoftext_headers() -> Vec<(String, String)> {
+  vec![
+    ("Host".to_string(), "api.soundoftext.com".to_string()),
+    (
+      "Referer".to_string(),
+      "https://soundoftext.com/".to_string(),
+    ),
+    ("Content-Type".to_string(), "application/json".to_string()),
+    ("Origin".to_string(), "https://soundoftext.com".to_string()),
+  ]
+}
@@ -0,0 +1,251 @@
+//! Downloading the audio files created by the tts api, to memory or to disk.
+
+use std::{
+  collections::VecDeque,
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+use crate::contracts::{self, http::GetOptions};
+
+use super::{Backoff, Jitter, Tts, TtsError};
+
+/// How downloads of audio files are retried. They fail in different ways than
+/// creating the audio, usually the file server is briefly unavailable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadRetry {
+  /// How many times a download is retried after the first attempt fails.
+  pub retries: u32,
+  pub backoff: Backoff,
+  /// Response statuses that are retried. Requests that fail without a response are always retried.
+  pub statuses: Vec<u16>,
+}
+
+impl Default for DownloadRetry {
+  fn default() -> Self {
+    Self {
+      retries: 2,
+      backoff: Backoff {
+        initial: Duration::from_millis(200),
+        max: Duration::from_secs(2),
+        jitter: Jitter::default(),
+      },
+      statuses: vec![408, 429, 500, 502, 503, 504],
+    }
+  }
+}
+
+/// The audio file of a chunk written to disk by `Tts::create_audio_files`.
+/// The file is deleted when this is dropped, unless `keep` is called.
+#[derive(Debug)]
+pub struct ChunkFile {
+  /// The position of the chunk in the message.
+  pub index: usize,
+  path: PathBuf,
+  keep: bool,
+}
+
+impl ChunkFile {
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Leaves the file on disk and returns its path. Deleting it is up to the caller.
+  pub fn keep(mut self) -> PathBuf {
+    self.keep = true;
+    std::mem::take(&mut self.path)
+  }
+}
+
+impl Drop for ChunkFile {
+  fn drop(&mut self) {
+    if self.keep {
+      return;
+    }
+
+    if let Err(err) = std::fs::remove_file(&self.path) {
+      if err.kind() != std::io::ErrorKind::NotFound {
+        error!(
+          "unable to delete chunk file. path={:?} error={:?}",
+          self.path, err
+        );
+      }
+    }
+  }
+}
+
+/// The downloads of the chunk files of a message started by `Tts::create_audio_files`.
+/// Stops the downloads that are still running when dropped.
+pub(super) struct ChunkDownloads {
+  /// The urls that were not downloaded yet and their index in the message.
+  urls: std::iter::Enumerate<std::vec::IntoIter<String>>,
+  /// Downloads started ahead of the caller, in the order of the chunks.
+  pub(super) pending: VecDeque<JoinHandle<Result<ChunkFile>>>,
+  message_id: u64,
+  dir: PathBuf,
+  /// How long the caller waited for downloads.
+  pub(super) spent: Duration,
+}
+
+impl ChunkDownloads {
+  /// Downloads the files at `urls` to `dir`.
+  pub(super) fn new(urls: Vec<String>, dir: &Path) -> Self {
+    Self {
+      urls: urls.into_iter().enumerate(),
+      pending: VecDeque::new(),
+      // Keeps the files of different messages apart.
+      message_id: rand::random(),
+      dir: dir.to_path_buf(),
+      spent: Duration::ZERO,
+    }
+  }
+
+  /// Starts downloading the next chunks until `count` downloads are pending.
+  pub(super) fn start(&mut self, tts: &Tts, count: usize) {
+    while self.pending.len() < count {
+      let (index, url) = match self.urls.next() {
+        None => return,
+        Some(next) => next,
+      };
+
+      let http_client = Arc::clone(&tts.http_client);
+      let policy = tts.config.download_retry.clone();
+      let path = self
+        .dir
+        .join(format!("{:016x}-{}.mp3", self.message_id, index));
+
+      self.pending.push_back(tokio::spawn(async move {
+        let bytes = download_file(http_client.as_ref(), &policy, &url).await?;
+
+        // The file is owned by the blocking task so a partial file is deleted when
+        // writing fails and a finished one when the download is aborted meanwhile.
+        tokio::task::spawn_blocking(move || {
+          let file = ChunkFile {
+            index,
+            path,
+            keep: false,
+          };
+
+          std::fs::write(&file.path, &bytes)
+            .with_context(|| format!("writing chunk file. path={:?}", file.path))?;
+
+          Ok::<_, anyhow::Error>(file)
+        })
+        .await?
+      }));
+    }
+  }
+}
+
+impl Drop for ChunkDownloads {
+  fn drop(&mut self) {
+    // The files of downloads that already finished are deleted with their result.
+    for task in &self.pending {
+      task.abort();
+    }
+  }
+}
+
+impl Tts {
+  /// Downloads the audio file at `url`, retrying as configured by `Config::download_retry`.
+  pub(super) async fn download(&self, url: &str) -> Result<Bytes> {
+    download_file(self.http_client.as_ref(), &self.config.download_retry, url).await
+  }
+}
+
+/// Downloads the audio file at `url`, retrying as configured by `policy`.
+/// Anything that is not a http url, like `Config::bleep_audio` and
+/// `Config::fallback_audio`, is read from disk.
+async fn download_file(
+  http_client: &dyn contracts::http::HttpClient,
+  policy: &DownloadRetry,
+  url: &str,
+) -> Result<Bytes> {
+  if !url.starts_with("http://") && !url.starts_with("https://") {
+    let bytes = tokio::fs::read(url)
+      .await
+      .with_context(|| format!("reading audio file. path={}", url))?;
+    return Ok(Bytes::from(bytes));
+  }
+
+  let mut attempt = 0;
+  let mut previous_delay = Duration::ZERO;
+
+  let response = loop {
+    let response = http_client
+      .get(
+        url,
+        Some(GetOptions {
+          headers: None,
+          query: None,
+          timeout: Some(Duration::from_secs(60)),
+        }),
+      )
+      .await;
+
+    let retryable = match &response {
+      Err(_) => true,
+      Ok(response) => policy.statuses.contains(&response.status),
+    };
+
+    if !retryable || attempt >= policy.retries {
+      break response;
+    }
+
+    let delay = policy
+      .backoff
+      .jittered_delay(attempt, previous_delay, &mut rand::thread_rng());
+    previous_delay = delay;
+    error!(
+      "error downloading audio file, will retry. url={} attempt={} delay={:?} status={:?}",
+      url,
+      attempt,
+      delay,
+      response.as_ref().map(|response| response.status)
+    );
+    tokio::time::sleep(delay).await;
+    attempt += 1;
+  };
+
+  let response = response.with_context(|| format!("downloading audio file. url={}", url))?;
+
+  if !(200..300).contains(&response.status) {
+    return Err(anyhow::anyhow!(
+      "unexpected status downloading audio file. url={} status={}",
+      url,
+      response.status
+    ));
+  }
+
+  if response.body.is_empty() {
+    return Err(anyhow::anyhow!(
+      "downloaded audio file is empty. url={}",
+      url
+    ));
+  }
+
+  Ok(response.body)
+}
+
+/// Fails with `TtsError::Timeout` when `future` doesn't complete within `deadline`.
+pub(super) async fn within_deadline<T>(
+  deadline: Option<Duration>,
+  future: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+  match deadline {
+    None => future.await,
+    Some(deadline) => match tokio::time::timeout(deadline, future).await {
+      Ok(result) => result,
+      Err(_) => {
+        error!("download deadline exceeded. deadline={:?}", deadline);
+        Err(TtsError::Timeout.into())
+      }
+    },
+  }
+}
@@ -0,0 +1,103 @@
+//! Keeps a message from being read again when it's sent more than once in a row,
+//! within `Config::duplicate_window`.
+
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use tracing::info;
+
+use super::{Tts, TtsError};
+
+/// Forgets a message recorded by `Tts::check_duplicate` when dropped, unless the message
+/// was read. A message that was not read is not a duplicate when it is sent again.
+pub(super) struct DuplicateGuard<'a> {
+  recent_messages: &'a Mutex<HashMap<String, Instant>>,
+  key: Option<String>,
+}
+
+impl DuplicateGuard<'_> {
+  /// Keeps the message recorded.
+  pub(super) fn commit(mut self) {
+    self.key = None;
+  }
+}
+
+impl Drop for DuplicateGuard<'_> {
+  fn drop(&mut self) {
+    if let Some(key) = self.key.take() {
+      self.recent_messages.lock().unwrap().remove(&key);
+    }
+  }
+}
+
+impl Tts {
+  /// Records `text` at `now` unless the same message was read within `Config::duplicate_window`.
+  /// Returns the key the message was recorded with.
+  pub(super) fn check_duplicate(
+    &self,
+    text: &str,
+    now: Instant,
+  ) -> Result<DuplicateGuard<'_>, TtsError> {
+    let window = match self.config.duplicate_window {
+      None => {
+        return Ok(DuplicateGuard {
+          recent_messages: &self.recent_messages,
+          key: None,
+        })
+      }
+      Some(window) => window,
+    };
+
+    let key = duplicate_key(text);
+
+    let mut recent_messages = self.recent_messages.lock().unwrap();
+
+    recent_messages.retain(|_, accepted_at| now.saturating_duration_since(*accepted_at) < window);
+
+    if recent_messages.contains_key(&key) {
+      info!("duplicate message. message={}", self.loggable_text(text));
+      return Err(TtsError::DuplicateMessage);
+    }
+
+    recent_messages.insert(key.clone(), now);
+
+    Ok(DuplicateGuard {
+      recent_messages: &self.recent_messages,
+      key: Some(key),
+    })
+  }
+}
+
+/// Returns what is compared to find duplicate messages: "Olá!!" and "olá" are the same message.
+fn duplicate_key(text: &str) -> String {
+  text
+    .split_whitespace()
+    .map(|word| {
+      word
+        .chars()
+        .filter(|character| character.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+    })
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_duplicate_key() {
+    let tests = vec![
+      ("", ""),
+      ("Olá!!", "olá"),
+      ("olá,   pessoal", "olá pessoal"),
+      ("... ok", "ok"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, duplicate_key(input), "input={}", input);
+    }
+  }
+}
@@ -0,0 +1,77 @@
+//! Decodes mp3 files to draw their waveform.
+
+use anyhow::{anyhow, Result};
+
+/// Returns `resolution` peaks of the audio in `mp3`, from 0 (silence) to 1 (the loudest
+/// possible sample). Each peak is the loudest sample in its slice of the audio.
+pub fn envelope(mp3: &[u8], resolution: usize) -> Result<Vec<f32>> {
+  let mut decoder = minimp3::Decoder::new(std::io::Cursor::new(mp3));
+
+  // The loudest channel of each sample.
+  let mut peaks: Vec<u16> = Vec::new();
+
+  loop {
+    match decoder.next_frame() {
+      Ok(frame) => {
+        let channels = frame.channels.max(1);
+        peaks.extend(frame.data.chunks(channels).map(|sample| {
+          sample
+            .iter()
+            .map(|value| value.unsigned_abs())
+            .max()
+            .unwrap_or(0)
+        }));
+      }
+      Err(minimp3::Error::Eof) => break,
+      // Garbage between frames is skipped by the decoder.
+      Err(minimp3::Error::SkippedData) => continue,
+      Err(err) => return Err(anyhow!("unable to decode mp3 file. error={:?}", err)),
+    }
+  }
+
+  if peaks.is_empty() {
+    return Err(anyhow!("mp3 file has no audio"));
+  }
+
+  let envelope = (0..resolution)
+    .map(|i| {
+      let start = i * peaks.len() / resolution;
+      let end = ((i + 1) * peaks.len() / resolution)
+        .max(start + 1)
+        .min(peaks.len());
+
+      let peak = peaks[start..end].iter().copied().max().unwrap_or(0);
+
+      f32::from(peak) / 32768.0
+    })
+    .collect();
+
+  Ok(envelope)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CLIP: &[u8] = include_bytes!("../../assets/henri.mp3");
+
+  #[test]
+  fn envelope_has_the_requested_resolution() -> Result<()> {
+    for resolution in [1, 64, 500] {
+      let envelope = envelope(CLIP, resolution)?;
+
+      assert_eq!(resolution, envelope.len());
+      assert!(envelope.iter().all(|peak| (0.0..=1.0).contains(peak)));
+      assert!(envelope.iter().any(|peak| *peak > 0.0));
+    }
+
+    assert_eq!(Vec::<f32>::new(), envelope(CLIP, 0)?);
+
+    Ok(())
+  }
+
+  #[test]
+  fn fails_without_audio() {
+    assert!(envelope(b"not an mp3 file", 10).is_err());
+  }
+}
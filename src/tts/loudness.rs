@@ -0,0 +1,123 @@
+//! Evens out the volume of the chunks of a message, the tts api doesn't create
+//! every audio file with the same volume.
+
+use anyhow::{anyhow, Result};
+
+use super::mp3;
+
+/// Returns the loudness of the audio in `mp3` as its RMS level in dBFS, 0 being the
+/// loudest possible audio. A rough approximation of LUFS that is enough to compare chunks.
+pub fn level(mp3: &[u8]) -> Result<f32> {
+  let mut decoder = minimp3::Decoder::new(std::io::Cursor::new(mp3));
+
+  let mut sum_of_squares = 0.0_f64;
+  let mut samples = 0_u64;
+
+  loop {
+    match decoder.next_frame() {
+      Ok(frame) => {
+        for sample in frame.data {
+          let sample = f64::from(sample) / 32768.0;
+          sum_of_squares += sample * sample;
+          samples += 1;
+        }
+      }
+      Err(minimp3::Error::Eof) => break,
+      // Garbage between frames is skipped by the decoder.
+      Err(minimp3::Error::SkippedData) => continue,
+      Err(err) => return Err(anyhow!("unable to decode mp3 file. error={:?}", err)),
+    }
+  }
+
+  if samples == 0 {
+    return Err(anyhow!("mp3 file has no audio"));
+  }
+
+  let rms = (sum_of_squares / samples as f64).sqrt();
+
+  // Silence would be infinitely quiet.
+  Ok((20.0 * rms.max(1e-9).log10()) as f32)
+}
+
+/// Changes the volume of the audio in `mp3` so its level is close to `target` dBFS.
+/// The volume changes in steps of `mp3::GAIN_STEP_DB`, the audio is not re-encoded.
+pub fn normalize(mp3: &[u8], target: f32) -> Result<Vec<u8>> {
+  let steps = ((target - level(mp3)?) / mp3::GAIN_STEP_DB).round() as i32;
+
+  Ok(mp3::adjust_gain(mp3, steps))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+  use crate::tts::{merge, Voice};
+
+  const CLIP: &[u8] = include_bytes!("../../assets/henri.mp3");
+
+  #[test]
+  fn gain_steps_change_the_level() -> Result<()> {
+    let quieter = mp3::adjust_gain(CLIP, -4);
+
+    let difference = level(CLIP)? - level(&quieter)?;
+    assert!((difference - 6.0).abs() < 1.0, "difference={}", difference);
+
+    Ok(())
+  }
+
+  #[test]
+  fn chunks_with_different_levels_are_merged_with_similar_levels() -> Result<()> {
+    let loud = CLIP.to_vec();
+    let quiet = mp3::adjust_gain(CLIP, -8);
+
+    let halves_difference = |merged: &[u8]| -> Result<f32> {
+      // Both chunks are the same clip so each one is half of the merged audio.
+      let mut decoder = minimp3::Decoder::new(std::io::Cursor::new(merged));
+      let mut samples: Vec<i16> = Vec::new();
+      while let Ok(frame) = decoder.next_frame() {
+        samples.extend(frame.data);
+      }
+      let (first, second) = samples.split_at(samples.len() / 2);
+      let rms = |samples: &[i16]| {
+        let sum: f64 = samples
+          .iter()
+          .map(|sample| f64::from(*sample) * f64::from(*sample))
+          .sum();
+        (sum / samples.len() as f64).sqrt()
+      };
+      Ok((20.0 * (rms(first) / rms(second)).log10()).abs() as f32)
+    };
+
+    let voice = Voice::default();
+    let merge_parts = |first: &[u8], second: &[u8]| {
+      merge::merge(
+        &[
+          merge::Part {
+            bytes: first,
+            voice: &voice,
+            pause_after: Duration::ZERO,
+          },
+          merge::Part {
+            bytes: second,
+            voice: &voice,
+            pause_after: Duration::ZERO,
+          },
+        ],
+        Duration::ZERO,
+        None,
+      )
+    };
+
+    let before = halves_difference(&merge_parts(&loud, &quiet))?;
+    let after = halves_difference(&merge_parts(
+      &normalize(&loud, -20.0)?,
+      &normalize(&quiet, -20.0)?,
+    ))?;
+
+    assert!(before > 10.0, "before={}", before);
+    assert!(after < mp3::GAIN_STEP_DB, "after={}", after);
+
+    Ok(())
+  }
+}
@@ -0,0 +1,189 @@
+//! Joins the audio files of a message into a single file.
+
+use std::time::Duration;
+
+use tracing::error;
+
+use super::{mp3, Voice};
+
+/// The downloaded audio file of a chunk.
+pub struct Part<'a> {
+  pub bytes: &'a [u8],
+  pub voice: &'a Voice,
+  /// Silence added after the chunk.
+  pub pause_after: Duration,
+}
+
+/// Joins the parts in order. Parts read by the same voice are joined without a gap
+/// and `voice_break` of silence is added where the voice changes, followed by
+/// `voice_change_cue` when there's one.
+pub fn merge(
+  parts: &[Part<'_>],
+  voice_break: Duration,
+  voice_change_cue: Option<&[u8]>,
+) -> Vec<u8> {
+  let mut merged = Vec::with_capacity(parts.iter().map(|part| part.bytes.len()).sum());
+
+  for (i, part) in parts.iter().enumerate() {
+    let audio = mp3::strip_id3(part.bytes);
+
+    merged.extend_from_slice(audio);
+
+    let voice_changes = parts
+      .get(i + 1)
+      .is_some_and(|next| next.voice != part.voice);
+
+    let silence = if voice_changes {
+      part.pause_after.saturating_add(voice_break)
+    } else {
+      part.pause_after
+    };
+
+    if !silence.is_zero() {
+      match mp3::first_frame_header(audio) {
+        None => error!(
+          "unable to add silence after chunk, it has no mp3 frames. chunk={}",
+          i
+        ),
+        Some(header) => merged.extend_from_slice(&mp3::silence(&header, silence)),
+      }
+    }
+
+    if let Some(cue) = voice_change_cue.filter(|_| voice_changes) {
+      merged.extend_from_slice(mp3::strip_id3(cue));
+    }
+  }
+
+  merged
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A mp3 frame preceded by an empty ID3 tag.
+  fn mp3_file(payload: u8) -> Vec<u8> {
+    let mut file = vec![b'I', b'D', b'3', 3, 0, 0, 0, 0, 0, 0];
+    let mut frame = vec![payload; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+    file.extend(frame);
+    file
+  }
+
+  #[test]
+  fn adds_a_break_only_where_the_voice_changes() {
+    let portuguese = Voice::default();
+    let english = Voice {
+      engine: String::from("google"),
+      voice: String::from("en-US"),
+    };
+
+    let (a, b, c) = (mp3_file(1), mp3_file(2), mp3_file(3));
+
+    let parts = vec![
+      Part {
+        bytes: &a,
+        voice: &portuguese,
+        pause_after: Duration::ZERO,
+      },
+      Part {
+        bytes: &b,
+        voice: &portuguese,
+        pause_after: Duration::ZERO,
+      },
+      Part {
+        bytes: &c,
+        voice: &english,
+        pause_after: Duration::ZERO,
+      },
+    ];
+
+    let header = mp3::first_frame_header(&a).unwrap();
+
+    // Each frame lasts about 26ms.
+    let expected = [
+      mp3::strip_id3(&a),
+      mp3::strip_id3(&b),
+      &mp3::silence(&header, Duration::from_millis(100)),
+      mp3::strip_id3(&c),
+    ]
+    .concat();
+
+    assert_eq!(4 * 417 + 3 * 417, expected.len());
+    assert_eq!(expected, merge(&parts, Duration::from_millis(100), None));
+
+    // Without a break the files are just joined.
+    assert_eq!(
+      [mp3::strip_id3(&a), mp3::strip_id3(&b), mp3::strip_id3(&c)].concat(),
+      merge(&parts, Duration::ZERO, None)
+    );
+  }
+
+  #[test]
+  fn adds_the_pause_after_each_part() {
+    let voice = Voice::default();
+
+    let (a, b) = (mp3_file(1), mp3_file(2));
+
+    let parts = vec![
+      Part {
+        bytes: &a,
+        voice: &voice,
+        pause_after: Duration::from_millis(50),
+      },
+      Part {
+        bytes: &b,
+        voice: &voice,
+        pause_after: Duration::ZERO,
+      },
+    ];
+
+    let header = mp3::first_frame_header(&a).unwrap();
+
+    assert_eq!(
+      [
+        mp3::strip_id3(&a),
+        &mp3::silence(&header, Duration::from_millis(50)),
+        mp3::strip_id3(&b),
+      ]
+      .concat(),
+      merge(&parts, Duration::from_millis(100), None)
+    );
+  }
+
+  #[test]
+  fn adds_the_cue_only_where_the_voice_changes() {
+    let portuguese = Voice::default();
+    let english = Voice {
+      engine: String::from("google"),
+      voice: String::from("en-US"),
+    };
+
+    let (a, b, c, cue) = (mp3_file(1), mp3_file(2), mp3_file(3), mp3_file(9));
+
+    let part = |bytes, voice| Part {
+      bytes,
+      voice,
+      pause_after: Duration::ZERO,
+    };
+    let parts = vec![
+      part(&a, &portuguese),
+      part(&b, &portuguese),
+      part(&c, &english),
+      part(&a, &portuguese),
+    ];
+
+    assert_eq!(
+      [
+        mp3::strip_id3(&a),
+        mp3::strip_id3(&b),
+        mp3::strip_id3(&cue),
+        mp3::strip_id3(&c),
+        mp3::strip_id3(&cue),
+        mp3::strip_id3(&a),
+      ]
+      .concat(),
+      merge(&parts, Duration::ZERO, Some(&cue))
+    );
+  }
+}
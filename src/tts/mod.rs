@@ -0,0 +1,5054 @@
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+  hash::{Hash, Hasher},
+  path::Path,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use crate::contracts::{
+  self,
+  http::{GetOptions, PostOptions},
+  tts::TtsCapabilities,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{
+  future::{AbortHandle, Abortable},
+  Stream, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+  sync::{
+    mpsc::{self, Receiver, Sender},
+    Semaphore,
+  },
+  task::JoinHandle,
+};
+use tracing::{error, info};
+
+mod acronyms;
+mod backoff;
+mod cache;
+mod chunks;
+mod credentials;
+mod downloads;
+mod duplicates;
+#[cfg(feature = "audio-analysis")]
+mod envelope;
+#[cfg(feature = "audio-analysis")]
+mod loudness;
+mod merge;
+mod mode;
+mod mp3;
+mod normalization;
+mod numbers;
+#[cfg(feature = "audio-analysis")]
+mod pcm;
+mod queue;
+mod rate_limit;
+mod segments;
+mod stats;
+mod template;
+
+pub use acronyms::AcronymReading;
+pub use backoff::{Backoff, Jitter};
+pub use chunks::{BreakFinder, ChunkOrder, LengthUnit};
+pub use credentials::CredentialsRefresher;
+pub use downloads::{ChunkFile, DownloadRetry};
+pub use mode::{Mode, Tuning};
+pub use normalization::{parse_emotes, AllowedCharacters, SynthesisWarning};
+pub use numbers::{
+  DateOrder, LargeAmountStyle, LargeAmounts, LongNumberPolicy, MathWords, NumberLocale,
+};
+#[cfg(feature = "audio-analysis")]
+pub use pcm::{PcmBuffer, PcmFormat};
+pub use queue::{QueueConfig, QueuedTts};
+pub use rate_limit::{default_role_policies, RolePolicy, SenderRateLimit, SenderRole};
+pub use stats::{Bucket, HistogramSnapshot, StatsSnapshot};
+pub use template::{Donation, DonationTemplate, TemplateField};
+
+use credentials::{check_auth_status, Credentials};
+use downloads::{within_deadline, ChunkDownloads};
+use rate_limit::SenderWindows;
+
+#[derive(Debug, Serialize)]
+struct CreateSoundRequest {
+  pub data: CreateSoundRequestData,
+  pub engine: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateSoundRequestData {
+  pub text: String,
+  pub voice: String,
+  /// Only sent when the api lets the sample rate be chosen.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sample_rate: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateSoundResponse {
+  pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetSoundLocationResponse {
+  pub status: String,
+  pub location: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+  /// Text read before every message, e.g. "Mensagem:".
+  pub message_prefix: Option<String>,
+  /// Text read after every message.
+  pub message_suffix: Option<String>,
+  /// The maximum number of graphemes, what a person would call a character, in a message.
+  pub max_message_len: Option<usize>,
+  /// What is done with messages longer than `max_message_len`.
+  pub overflow_policy: OverflowPolicy,
+  /// Appended to messages that were truncated.
+  pub truncation_ellipsis: String,
+  /// Only the first sentences of a message are read, the rest is skipped.
+  pub max_sentences: Option<usize>,
+  /// Ends the last sentence read with `truncation_ellipsis` when sentences were skipped
+  /// because of `max_sentences`.
+  pub mark_dropped_sentences: bool,
+  /// When set, every character that is not allowed is removed from messages
+  /// before they are read, voice and pause markup included.
+  pub allowed_characters: Option<AllowedCharacters>,
+  /// Words that are not allowed to be read.
+  pub blocked_words: Vec<String>,
+  /// What is done with messages that contain words in `blocked_words`.
+  pub blocked_word_policy: BlockedWordPolicy,
+  /// Path or url of an audio file, like a beep, played in place of the chunks with
+  /// blocked words when `blocked_word_policy` is `BleepChunk`. The chunks are
+  /// skipped when there's none.
+  pub bleep_audio: Option<String>,
+  /// Also blocks words that are blocked once digits and symbols are read as the letters
+  /// they look like, "p0rr@" as "porra". Only used to find blocked words, the rest of
+  /// the message is read as written. Off by default, it may block words that only
+  /// look like blocked words.
+  pub match_leetspeak: bool,
+  /// The voice used for each tier. Tiers without a voice use `Voice::default()`.
+  pub tier_voices: HashMap<Tier, Voice>,
+  /// Voices tried, in order, when every attempt to create audio with the voice
+  /// of the message fails.
+  pub fallback_voices: Vec<Voice>,
+  /// Path or url of an audio file, like "não consegui ler a mensagem", returned
+  /// when every voice fails so the message is at least acknowledged.
+  pub fallback_audio: Option<String>,
+  /// Messages that start with one of these prefixes followed by a word, like
+  /// "!play", are chat commands for bots. Empty by default, so no message is a command.
+  pub command_prefixes: Vec<String>,
+  /// What is done with messages that are chat commands.
+  pub command_policy: CommandPolicy,
+  /// What is done with the parts of the message between spoiler markers: "||spoiler||".
+  pub spoiler_policy: SpoilerPolicy,
+  /// Emoji that are read as the name they are mapped to. Emoji that are not
+  /// in the map are removed from the message.
+  pub emoji_names: HashMap<String, String>,
+  /// What is done with messages that only mention people. The "@" of mentions
+  /// in other messages is removed so only the name is read.
+  pub mention_only_policy: MentionOnlyPolicy,
+  /// The same emoji repeated in a row is read at most this many times.
+  pub max_emoji_repeats: Option<usize>,
+  /// Text emoticons, like ":)", and the phrase they are read as. An empty phrase removes the emoticon.
+  pub emoticons: HashMap<String, String>,
+  /// Chat emotes written as words, like "PogChamp", and how they are read. Emotes read
+  /// as an empty string are removed. More emotes can be loaded with `parse_emotes`.
+  pub emotes: HashMap<String, String>,
+  /// How some words are read, like "iPhone" as "aifone". Words written with their own
+  /// casing only match when written the same way, words written in lowercase match
+  /// however they are written. Applied before `read_acronyms`.
+  pub pronunciations: HashMap<String, String>,
+  /// A word repeated in a row more than this many times, like "obrigado obrigado obrigado
+  /// obrigado", is handled according to `repeated_word_policy`. Case is ignored. Off by default.
+  pub max_word_repeats: Option<usize>,
+  pub repeated_word_policy: RepeatedWordPolicy,
+  /// What is done with repeated punctuation like "obrigado!!!" and "então......".
+  pub punctuation_policy: PunctuationPolicy,
+  /// The maximum length of the text sent to the tts api in a single request.
+  /// A text of exactly this length is sent in a single request.
+  pub max_chunk_len: usize,
+  /// How `max_chunk_len` is measured.
+  pub length_unit: LengthUnit,
+  /// The last chunk of a message is joined with the chunk before it when it is shorter
+  /// than this, as long as the joined chunk is at most `chunk_len_tolerance` longer
+  /// than `max_chunk_len`. Zero keeps every chunk.
+  pub min_chunk_len: usize,
+  /// How much longer than `max_chunk_len` a chunk can get when a short last chunk is joined with it.
+  pub chunk_len_tolerance: usize,
+  /// Where text without punctuation that is longer than `max_chunk_len` is split.
+  pub break_finder: BreakFinder,
+  /// The order the chunks of a message are synthesized, returned and played in.
+  pub chunk_order: ChunkOrder,
+  /// Speeds up the reading towards the end of long messages. Off by default.
+  /// Only works with apis that report `TtsCapabilities::rate_control`. soundoftext,
+  /// the only api supported, doesn't let the rate be chosen, so the rate is never sent
+  /// and messages fail with `TtsError::RateControlUnsupported` when it is set.
+  pub speed_ramp: Option<SpeedRamp>,
+  /// The sample rate, in Hz, of the audio requested from the tts api. Lower rates
+  /// use less bandwidth. `None` uses the rate chosen by the api. Must be one of
+  /// `TtsCapabilities::sample_rates`, messages fail with `TtsError::UnsupportedSampleRate`
+  /// otherwise.
+  pub sample_rate: Option<u32>,
+  /// The sample rates, in Hz, the tts api lets the audio be requested in. Empty by
+  /// default since soundoftext decides the sample rate.
+  pub api_sample_rates: Vec<u32>,
+  /// Silence added where the voice changes when the chunks of a message are merged
+  /// into a single file. Chunks read by the same voice are joined without a gap.
+  pub voice_break: Duration,
+  /// The longest silence after a piece of a message, pauses written in the message
+  /// with `[pause=<milliseconds>]` included.
+  pub max_pause: Duration,
+  /// What is done with voice and pause markup written in messages. Removed by default.
+  pub markup_policy: MarkupPolicy,
+  /// A short mp3 file, like a chime, played after `voice_break` where the voice changes
+  /// so listeners aren't confused by the new voice. Should have the same sample rate
+  /// as the audio created by the tts api.
+  pub voice_change_cue: Option<Bytes>,
+  /// The loudness, in dBFS, the chunks of a message are adjusted to before they are merged
+  /// so the volume doesn't jump between them. `None` merges them as they are.
+  #[cfg(feature = "audio-analysis")]
+  pub loudness_target: Option<f32>,
+  /// Reads words written in capital letters as words or letter by letter,
+  /// "NASA" as "nasa" and "FBI" as "F-B-I". Off by default.
+  pub read_acronyms: bool,
+  /// How some words written in capital letters are read, instead of guessing it.
+  pub acronym_readings: HashMap<String, AcronymReading>,
+  /// Reads amounts of money as words: "R$ 10,05" as "dez reais e cinco centavos".
+  pub read_currency: bool,
+  /// Amounts of money above the threshold are read in a short form, "quase um bilhão
+  /// de reais", instead of every digit. `None` reads them in full.
+  pub large_amounts: Option<LargeAmounts>,
+  /// Reads ordinals and fractions as words: "1º" as "primeiro" and "3/4" as "três quartos".
+  /// Only halves, thirds and quarters smaller than one are read as fractions.
+  pub expand_ordinals_and_fractions: bool,
+  /// Reads times as words: "14:30" as "quatorze e trinta". Off by default.
+  pub read_times: bool,
+  /// How dates are written in the messages. They are read as words: "25/12" as
+  /// "vinte e cinco de dezembro". Dates without a year that could be a score, like
+  /// "10/10", are only read after "dia". `None` leaves them as written.
+  pub date_order: Option<DateOrder>,
+  /// How "%", "+", "-" and "=" are read next to numbers, "-" only when it starts a
+  /// negative number. `None` leaves them as written.
+  pub math_words: Option<MathWords>,
+  /// How numbers with separators, like "1.234,56", are written in the messages.
+  /// `NumberLocale::Auto` picks it from the voice of the message. `None` leaves them
+  /// to the voice.
+  pub number_locale: Option<NumberLocale>,
+  /// Numbers with more digits than this, like transaction ids, are not read digit by digit.
+  /// `None`, the default, leaves them to the voice.
+  pub long_number_threshold: Option<usize>,
+  /// What is done with numbers longer than `long_number_threshold`.
+  pub long_number_policy: LongNumberPolicy,
+  /// Defaults for how hard the tts api is hit, the fields below override them.
+  pub mode: Mode,
+  /// Overrides `Tuning::max_concurrency` of the mode.
+  pub max_concurrency: Option<usize>,
+  /// The maximum number of chunks being created at the same time by a voice, across
+  /// every message, keyed by the name of the voice like "pt-BR". Voices that are
+  /// not in the map are only limited by `max_concurrency`.
+  pub voice_concurrency: HashMap<String, usize>,
+  /// Overrides `Tuning::retries` of the mode.
+  pub retries: Option<u32>,
+  /// Overrides `Tuning::backoff` of the mode.
+  pub backoff: Option<Backoff>,
+  /// Overrides `Tuning::poll_interval` of the mode.
+  pub poll_interval: Option<Duration>,
+  /// How downloads of audio files are retried, `retries` and `backoff` only apply to creating audio.
+  pub download_retry: DownloadRetry,
+  /// The maximum time spent downloading the audio files of a message, and merging them
+  /// when they are merged, across every chunk and retry. Creating the audio is not
+  /// included. Downloads still running when it is reached are stopped. `None` doesn't limit it.
+  pub download_deadline: Option<Duration>,
+  /// How many chunks after the one requested from `Tts::create_audio_files` are
+  /// downloaded in the background, to avoid gaps between chunks when they are played
+  /// as they are downloaded. Limited by `Tuning::max_concurrency`.
+  pub prefetch_chunks: usize,
+  /// The maximum number of chunks waiting for their audio file to be ready at the same time,
+  /// across every message. Each of them asks the api if the file is ready every `poll_interval`.
+  pub max_concurrent_polls: usize,
+  /// Headers sent with every request to the tts api to authenticate it,
+  /// like ("Authorization", "Bearer <token>").
+  pub auth_headers: Vec<(String, String)>,
+  /// Called to replace `auth_headers` when the api answers that they expired.
+  /// The request that failed is retried once with the new headers.
+  pub refresh_credentials: Option<CredentialsRefresher>,
+  /// Logs the length and a hash of messages instead of their text, since
+  /// messages may contain personal information.
+  pub redact_logs: bool,
+  /// How many of the latest requests to the tts api are kept for `Tts::last_exchanges`.
+  /// `None` keeps none.
+  pub captured_exchanges: Option<usize>,
+  /// Limits how many messages each sender can have read through `Tts::create_audio_for_sender`.
+  /// `None` doesn't limit them.
+  pub sender_rate_limit: Option<SenderRateLimit>,
+  /// Messages that are the same as a message read less than this long ago are rejected
+  /// with `TtsError::DuplicateMessage`, even when their audio is cached or the cache is
+  /// bypassed. Messages are compared ignoring case, punctuation and who sent them.
+  /// `None` reads every message.
+  pub duplicate_window: Option<Duration>,
+  /// Voices assigned to the senders of `Tts::create_audio_for_sender`.
+  /// `None` reads every sender with the voice of the tier.
+  pub sender_voices: Option<SenderVoices>,
+  /// How the messages of each kind of sender are treated. Roles that are not in the
+  /// map are treated like `SenderRole::Regular` without a policy.
+  pub role_policies: HashMap<SenderRole, RolePolicy>,
+  /// Messages matching the template are read as donations by `Tts::create_audio_from_input`.
+  pub donation_template: Option<DonationTemplate>,
+  /// Read before the donations of `Tts::create_first_donation_audio`.
+  /// "{donor}" is replaced with the name of the donor.
+  pub first_donation_intro: String,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      message_prefix: None,
+      message_suffix: None,
+      max_message_len: None,
+      overflow_policy: OverflowPolicy::default(),
+      truncation_ellipsis: String::from("…"),
+      max_sentences: None,
+      mark_dropped_sentences: false,
+      allowed_characters: None,
+      blocked_words: Vec::new(),
+      blocked_word_policy: BlockedWordPolicy::default(),
+      bleep_audio: None,
+      match_leetspeak: false,
+      tier_voices: HashMap::new(),
+      fallback_voices: Vec::new(),
+      fallback_audio: None,
+      spoiler_policy: SpoilerPolicy::default(),
+      emoji_names: normalization::default_emoji_names(),
+      mention_only_policy: MentionOnlyPolicy::default(),
+      command_prefixes: Vec::new(),
+      command_policy: CommandPolicy::default(),
+      max_emoji_repeats: Some(1),
+      emoticons: normalization::default_emoticons(),
+      emotes: normalization::default_emotes(),
+      pronunciations: normalization::default_pronunciations(),
+      max_word_repeats: None,
+      repeated_word_policy: RepeatedWordPolicy::default(),
+      punctuation_policy: PunctuationPolicy::default(),
+      max_chunk_len: 200,
+      length_unit: LengthUnit::default(),
+      min_chunk_len: 0,
+      chunk_len_tolerance: 20,
+      break_finder: BreakFinder::default(),
+      chunk_order: ChunkOrder::default(),
+      speed_ramp: None,
+      sample_rate: None,
+      api_sample_rates: Vec::new(),
+      voice_break: Duration::from_millis(300),
+      max_pause: Duration::from_secs(3),
+      markup_policy: MarkupPolicy::default(),
+      voice_change_cue: None,
+      #[cfg(feature = "audio-analysis")]
+      loudness_target: None,
+      read_acronyms: false,
+      acronym_readings: HashMap::new(),
+      read_currency: true,
+      large_amounts: Some(LargeAmounts::default()),
+      expand_ordinals_and_fractions: true,
+      read_times: false,
+      date_order: Some(DateOrder::default()),
+      math_words: Some(MathWords::portuguese()),
+      number_locale: Some(NumberLocale::default()),
+      long_number_threshold: None,
+      long_number_policy: LongNumberPolicy::default(),
+      mode: Mode::default(),
+      max_concurrency: None,
+      voice_concurrency: HashMap::new(),
+      retries: None,
+      backoff: None,
+      poll_interval: None,
+      download_retry: DownloadRetry::default(),
+      download_deadline: Some(Duration::from_secs(60)),
+      prefetch_chunks: 0,
+      max_concurrent_polls: 4,
+      auth_headers: Vec::new(),
+      refresh_credentials: None,
+      redact_logs: !cfg!(debug_assertions),
+      captured_exchanges: None,
+      sender_rate_limit: None,
+      duplicate_window: None,
+      sender_voices: None,
+      role_policies: default_role_policies(),
+      donation_template: None,
+      first_donation_intro: String::from("primeira doação de {donor}!"),
+    }
+  }
+}
+
+impl Config {
+  /// Returns the settings of the mode with the overrides applied.
+  pub fn tuning(&self) -> Tuning {
+    let defaults = self.mode.tuning();
+
+    Tuning {
+      max_concurrency: self
+        .max_concurrency
+        .unwrap_or(defaults.max_concurrency)
+        .max(1),
+      retries: self.retries.unwrap_or(defaults.retries),
+      backoff: self.backoff.unwrap_or(defaults.backoff),
+      poll_interval: self.poll_interval.unwrap_or(defaults.poll_interval),
+    }
+  }
+}
+
+/// Speeds up the reading of messages with many chunks so they don't drag on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedRamp {
+  /// Messages with more chunks than this are sped up.
+  pub min_chunks: usize,
+  /// The speaking rate of the last chunk in percent of the normal rate, e.g. 125.
+  /// The rate grows linearly from the normal rate in the first chunk to it.
+  pub max_rate: u32,
+}
+
+impl SpeedRamp {
+  /// Returns the speaking rate of each of the `chunks` chunks of a message,
+  /// `None` when the chunk is read at the normal rate.
+  pub fn rates(&self, chunks: usize) -> Vec<Option<u32>> {
+    if chunks <= self.min_chunks || chunks < 2 || self.max_rate <= 100 {
+      return vec![None; chunks];
+    }
+
+    let increase = (self.max_rate - 100) as usize;
+
+    (0..chunks)
+      .map(|i| match 100 + increase * i / (chunks - 1) {
+        100 => None,
+        rate => Some(rate as u32),
+      })
+      .collect()
+  }
+}
+
+/// Voices assigned to senders so each one, like a donor, is always read by the same voice.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SenderVoices {
+  /// Senders without an override are read by one of these voices, picked from their id.
+  /// A sender gets the same voice every time as long as the pool doesn't change.
+  pub pool: Vec<Voice>,
+  /// The voice of specific senders.
+  pub overrides: HashMap<String, Voice>,
+}
+
+impl SenderVoices {
+  /// Returns the voice of `sender_id`, `None` when there's no override and the pool is empty.
+  pub fn voice_for(&self, sender_id: &str) -> Option<Voice> {
+    if let Some(voice) = self.overrides.get(sender_id) {
+      return Some(voice.clone());
+    }
+
+    if self.pool.is_empty() {
+      return None;
+    }
+
+    // FNV-1a, unlike `DefaultHasher` it is the same in every version of Rust
+    // so senders keep their voices across restarts.
+    let hash = sender_id
+      .bytes()
+      .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+      });
+
+    Some(self.pool[(hash % self.pool.len() as u64) as usize].clone())
+  }
+}
+
+/// What is done with messages longer than `Config::max_message_len`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+  /// The end of the message is removed.
+  #[default]
+  Truncate,
+  /// The message is not read.
+  Reject,
+}
+
+/// What is done with messages that contain words in `Config::blocked_words`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlockedWordPolicy {
+  /// The blocked words are removed and the rest of the message is read.
+  #[default]
+  Remove,
+  /// The message is not read.
+  Reject,
+  /// The message is divided in chunks as usual and only the chunks with blocked
+  /// words are not read, so the rest of a long message is still read.
+  SkipChunk,
+  /// Same as `SkipChunk` but `Config::bleep_audio` is played in place of the
+  /// chunks with blocked words.
+  BleepChunk,
+}
+
+/// What was done with a chunk of the message because of `Config::blocked_word_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkFilter {
+  /// The chunk has no blocked words and is read.
+  Allowed,
+  /// `Config::bleep_audio` is played in place of the chunk.
+  Bleeped,
+  /// The chunk is not read.
+  Skipped,
+}
+
+/// What is done with messages that start with a chat command, like "!play música".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommandPolicy {
+  /// The message is not read, it was written for a bot.
+  #[default]
+  Skip,
+  /// The command is removed and the rest of the message is read.
+  Strip,
+}
+
+/// What is done with the parts of a message between spoiler markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpoilerPolicy {
+  /// The markers are removed and the spoiler is read like the rest of the message.
+  Strip,
+  /// The spoiler is not read.
+  #[default]
+  Skip,
+  /// The spoiler is read after saying "spoiler".
+  Announce,
+}
+
+/// What is done with messages that only mention people, like "@streamer @mod".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MentionOnlyPolicy {
+  /// The message is not read, it has nothing to say.
+  #[default]
+  Skip,
+  /// The message is read as "mencionou streamer e mod".
+  Announce,
+}
+
+/// What is done with a word repeated more than `Config::max_word_repeats` times in a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatedWordPolicy {
+  /// The word is read `Config::max_word_repeats` times.
+  #[default]
+  Limit,
+  /// The word is read once followed by "várias vezes".
+  Announce,
+}
+
+/// What is done with voice and pause markup, like "[voice=en-US]", written in messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkupPolicy {
+  /// The message is read in the voices and with the pauses in the markup.
+  /// Only for messages written by trusted people, like the streamer.
+  Apply,
+  /// The markup is removed so viewers can't change how their messages are read.
+  /// The message prefix and suffix are not changed.
+  #[default]
+  Remove,
+}
+
+/// What is done with runs of repeated punctuation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PunctuationPolicy {
+  /// Punctuation is sent to the tts api as written.
+  Keep,
+  /// "obrigado!!!" is read as "obrigado!" and "então......" as "então...".
+  #[default]
+  Collapse,
+  /// Runs of two or more punctuation marks become a pause that grows with the
+  /// length of the run, so "então......" has a longer pause than "então..".
+  Pause {
+    /// How long the pause is for each punctuation mark in the run.
+    per_mark: Duration,
+    /// Pauses never get longer than this.
+    max: Duration,
+  },
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum TtsError {
+  #[error("the message is empty")]
+  EmptyMessage,
+  #[error("the message has {len} characters but the maximum is {max}")]
+  MessageTooLong { len: usize, max: usize },
+  #[error("the message contains a blocked word: {0}")]
+  BlockedWord(String),
+  #[error("the message is written in a script the voice can't read")]
+  UnsupportedScript,
+  #[error("the message has nothing that can be read after preprocessing")]
+  NoSpeakableContent,
+  /// The tts api answered without a body. It usually works when retried.
+  #[error("the tts api returned an empty response")]
+  EmptyResponse,
+  #[error("the synthesis was cancelled")]
+  Cancelled,
+  #[error("the sender {0} sent too many messages")]
+  SenderRateLimited(String),
+  #[error("the same message was read recently")]
+  DuplicateMessage,
+  #[error("the tts api can't create audio with a sample rate of {0}hz")]
+  UnsupportedSampleRate(u32),
+  #[error("the tts api can't change the speaking rate")]
+  RateControlUnsupported,
+  #[error("downloading the audio took longer than the deadline")]
+  Timeout,
+  /// The api answered 401. Requests are retried once after `Config::refresh_credentials`.
+  #[error("the credentials of the tts api expired")]
+  CredentialsExpired,
+  /// The api answered 403, or 401 right after the credentials were refreshed.
+  #[error("the credentials of the tts api are not valid")]
+  InvalidCredentials,
+}
+
+/// How good the audio should sound. Better tiers are usually slower or more expensive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Tier {
+  Fast,
+  #[default]
+  Balanced,
+  HighQuality,
+}
+
+/// The engine and voice used by the tts api to read a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Voice {
+  pub engine: String,
+  pub voice: String,
+}
+
+impl Default for Voice {
+  fn default() -> Self {
+    Self {
+      engine: String::from("google"),
+      voice: String::from("pt-BR"),
+    }
+  }
+}
+
+/// Options that apply to a single call instead of every message.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+  pub tier: Tier,
+  /// Creates the audio with the tts api even when it is in the cache.
+  pub bypass_cache: bool,
+  /// When bypassing the cache, replaces the cached audio with the audio created by this call.
+  pub refresh_cache: bool,
+  /// Identifies the call so it can be cancelled with `Tts::cancel` while the audio is created.
+  pub correlation_id: Option<String>,
+  /// Reads the message with this voice instead of the voice of `tier`.
+  pub voice: Option<Voice>,
+}
+
+/// An audio file created by the tts api.
+#[derive(Debug)]
+pub struct AudioChunk {
+  pub url: String,
+  /// The contents of the mp3 file.
+  pub bytes: Bytes,
+}
+
+/// The audio of a message and its waveform.
+#[cfg(feature = "audio-analysis")]
+#[derive(Debug)]
+pub struct AudioPreview {
+  /// The mp3 file containing the whole message.
+  pub audio: Bytes,
+  /// The peaks of the audio from 0 to 1, evenly spaced in time.
+  pub envelope: Vec<f32>,
+}
+
+/// How the audio for a chunk of the message was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkDetails {
+  pub url: String,
+  /// The voice that created the audio. Differs from the voice of the message
+  /// when it failed and a fallback voice was used.
+  pub voice: Voice,
+  /// How many requests were made to create the audio, across every voice tried.
+  /// Zero when the url came from the cache.
+  pub attempts: u32,
+  /// Silence the player should leave after the chunk is played.
+  pub pause_after: Duration,
+  /// Speaking rate in percent of the normal rate, set by `Config::speed_ramp`.
+  pub rate: Option<u32>,
+}
+
+/// The result of creating audio for a message.
+#[derive(Debug)]
+pub struct SynthesisDetails {
+  /// Urls of the mp3 files containing the message, in the order they should be played.
+  pub urls: Vec<String>,
+  /// How each chunk was created, in the same order as `urls`.
+  /// Empty when `Config::fallback_audio` was returned.
+  pub chunks: Vec<ChunkDetails>,
+  /// Transformations applied to the message that did not prevent it from being read.
+  pub warnings: Vec<SynthesisWarning>,
+  /// What was done with each chunk the message was divided in, in order. Skipped
+  /// chunks are not in `urls` and `chunks`. Empty when `Config::fallback_audio` was returned.
+  pub filtered: Vec<ChunkFilter>,
+}
+
+/// A request to the tts api and what it returned, kept to investigate failures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpExchange {
+  pub method: &'static str,
+  pub url: String,
+  /// What was requested, with the text of the message redacted when `Config::redact_logs` is set.
+  pub request: Option<String>,
+  /// The status code of the response, `None` when there was no response.
+  pub status: Option<u16>,
+  /// The response body, whatever its status, or the error when there was no response.
+  pub response: Result<String, String>,
+}
+
+/// What is synthesized as a single message.
+enum MessageInput {
+  /// A message that may contain voice and pause markup.
+  Text(String),
+  /// Pieces of text divided by the caller and the voice that reads each one.
+  Segments(Vec<(String, String)>),
+}
+
+/// A piece of text sent to the tts api in a single request.
+#[derive(Debug)]
+struct PlannedChunk {
+  text: String,
+  voice: Voice,
+  pause_after: Duration,
+  rate: Option<u32>,
+}
+
+/// An update about the synthesis of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SynthesisProgress {
+  /// The message was divided in `chunks` chunks.
+  Started { chunks: usize },
+  /// The audio of the chunk at `index` was created. Chunks may be created out of order.
+  ChunkCreated { index: usize, url: String },
+  /// The audio of every chunk was created. Not sent when the synthesis fails.
+  Finished,
+}
+
+/// How many progress updates are buffered before the synthesis waits for the receiver.
+const PROGRESS_CHANNEL_CAPACITY: usize = 32;
+
+pub struct Tts {
+  config: Config,
+  http_client: Arc<dyn contracts::http::HttpClient>,
+  /// Caches the url of the audio file created for a piece of text.
+  cache: Option<Arc<dyn contracts::cache::Cache>>,
+  /// Tasks spawned to do work that the caller does not wait for, like writing to the cache.
+  background_tasks: Mutex<Vec<JoinHandle<()>>>,
+  /// Limits how many chunks are polling the api at the same time.
+  polls: Semaphore,
+  /// Limits how many chunks each voice in `Config::voice_concurrency` creates at the same time.
+  voice_limits: HashMap<String, Semaphore>,
+  /// The latest requests to the tts api, the oldest first.
+  exchanges: Mutex<VecDeque<HttpExchange>>,
+  /// Syntheses started with a correlation id that have not finished yet.
+  operations: Mutex<Operations>,
+  /// Lengths and chunk counts of the messages synthesized.
+  stats: stats::Stats,
+  /// Starts as `Config::auth_headers` and is replaced when the credentials are refreshed.
+  credentials: Mutex<Credentials>,
+  /// Held while the credentials are refreshed so chunks that find them expired at
+  /// the same time refresh them once.
+  refreshing: tokio::sync::Mutex<()>,
+  /// When the recent messages of each sender were accepted, the oldest first.
+  senders: Mutex<SenderWindows>,
+  /// When each recent message was accepted, keyed by `duplicate_key`.
+  recent_messages: Mutex<HashMap<String, Instant>>,
+}
+
+/// Syntheses that can be cancelled, keyed by a number unique to each synthesis
+/// since callers may reuse correlation ids.
+#[derive(Default)]
+struct Operations {
+  next_key: u64,
+  active: HashMap<u64, (String, AbortHandle)>,
+}
+
+/// Stops tracking a synthesis when it finishes or is dropped.
+struct OperationGuard<'a> {
+  operations: &'a Mutex<Operations>,
+  key: u64,
+}
+
+impl Drop for OperationGuard<'_> {
+  fn drop(&mut self) {
+    self.operations.lock().unwrap().active.remove(&self.key);
+  }
+}
+
+impl Tts {
+  pub fn new(
+    config: Config,
+    http_client: Arc<dyn contracts::http::HttpClient>,
+    cache: Option<Arc<dyn contracts::cache::Cache>>,
+  ) -> Self {
+    let voice_limits = config
+      .voice_concurrency
+      .iter()
+      .map(|(voice, limit)| (voice.clone(), Semaphore::new((*limit).max(1))))
+      .collect();
+
+    Self {
+      polls: Semaphore::new(config.max_concurrent_polls.max(1)),
+      voice_limits,
+      credentials: Mutex::new(Credentials {
+        headers: config.auth_headers.clone(),
+        generation: 0,
+      }),
+      refreshing: tokio::sync::Mutex::new(()),
+      config,
+      http_client,
+      cache,
+      background_tasks: Mutex::new(Vec::new()),
+      exchanges: Mutex::new(VecDeque::new()),
+      operations: Mutex::new(Operations::default()),
+      stats: stats::Stats::default(),
+      senders: Mutex::new(SenderWindows::default()),
+      recent_messages: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Returns histograms of the lengths and chunk counts of the messages synthesized,
+  /// including the ones whose synthesis failed after being split in chunks.
+  pub fn stats(&self) -> StatsSnapshot {
+    self.stats.snapshot()
+  }
+
+  /// Returns the correlation ids of the syntheses in progress.
+  pub fn in_flight(&self) -> Vec<String> {
+    let operations = self.operations.lock().unwrap();
+    let mut ids: Vec<String> = operations
+      .active
+      .values()
+      .map(|(correlation_id, _)| correlation_id.clone())
+      .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+  }
+
+  /// Stops the syntheses started with `correlation_id`, including the requests of their
+  /// chunks. They return `TtsError::Cancelled`. Returns false when there was none.
+  pub fn cancel(&self, correlation_id: &str) -> bool {
+    let operations = self.operations.lock().unwrap();
+
+    let mut found = false;
+    for (id, handle) in operations.active.values() {
+      if id == correlation_id {
+        handle.abort();
+        found = true;
+      }
+    }
+
+    info!(
+      "cancelling synthesis. correlation_id={} found={}",
+      correlation_id, found
+    );
+
+    found
+  }
+
+  fn track_operation(&self, correlation_id: String, handle: AbortHandle) -> OperationGuard<'_> {
+    let mut operations = self.operations.lock().unwrap();
+
+    let key = operations.next_key;
+    operations.next_key += 1;
+    operations.active.insert(key, (correlation_id, handle));
+
+    OperationGuard {
+      operations: &self.operations,
+      key,
+    }
+  }
+
+  /// Returns the latest requests to the tts api, the oldest first.
+  /// Empty unless `Config::captured_exchanges` is set.
+  pub fn last_exchanges(&self) -> Vec<HttpExchange> {
+    self.exchanges.lock().unwrap().iter().cloned().collect()
+  }
+
+  fn capture_exchange(
+    &self,
+    method: &'static str,
+    url: &str,
+    request: Option<&str>,
+    response: std::result::Result<(u16, &[u8]), &anyhow::Error>,
+  ) {
+    let capacity = match self.config.captured_exchanges {
+      None | Some(0) => return,
+      Some(capacity) => capacity,
+    };
+
+    let mut exchanges = self.exchanges.lock().unwrap();
+
+    while exchanges.len() >= capacity {
+      exchanges.pop_front();
+    }
+
+    exchanges.push_back(HttpExchange {
+      method,
+      url: url.to_string(),
+      request: request.map(String::from),
+      status: response.as_ref().ok().map(|(status, _)| *status),
+      response: response
+        .map(|(_, body)| String::from_utf8_lossy(body).to_string())
+        .map_err(|err| format!("{:?}", err)),
+    });
+  }
+
+  /// Waits for the background tasks, like pending cache writes, to finish.
+  #[tracing::instrument(skip_all)]
+  pub async fn shutdown(&self) {
+    let tasks = std::mem::take(&mut *self.background_tasks.lock().unwrap());
+
+    info!("waiting for background tasks. tasks={}", tasks.len());
+
+    for task in tasks {
+      if let Err(err) = task.await {
+        error!("background task failed. error={:?}", err);
+      }
+    }
+  }
+
+  fn spawn_background_task<F>(&self, future: F)
+  where
+    F: std::future::Future<Output = ()> + Send + 'static,
+  {
+    let mut tasks = self.background_tasks.lock().unwrap();
+    tasks.retain(|task| !task.is_finished());
+    tasks.push(tokio::spawn(future));
+  }
+
+  /// Checks that `text` would be read without creating any audio.
+  /// Returns the error that creating audio for `text` would return.
+  pub fn validate(&self, text: &str) -> Result<(), TtsError> {
+    self
+      .prepare_text(text, &self.message_voice(&CallOptions::default()))
+      .map(|_| ())
+  }
+
+  /// Rejects messages that should not be read.
+  fn check_message(
+    &self,
+    original: &str,
+    message: &normalization::Preprocessed,
+  ) -> Result<(), TtsError> {
+    if original.trim().is_empty() {
+      return Err(TtsError::EmptyMessage);
+    }
+
+    if self.config.blocked_word_policy == BlockedWordPolicy::Reject {
+      if let Some(word) = normalization::find_blocked_word(
+        original,
+        &self.config.blocked_words,
+        self.config.match_leetspeak,
+      ) {
+        return Err(TtsError::BlockedWord(word.to_string()));
+      }
+    }
+
+    if self.config.overflow_policy == OverflowPolicy::Reject {
+      for warning in message.warnings.iter() {
+        if let SynthesisWarning::Truncated { original_len, .. } = warning {
+          return Err(TtsError::MessageTooLong {
+            len: *original_len,
+            // SAFETY: messages are only truncated when there's a maximum length.
+            max: self.config.max_message_len.unwrap(),
+          });
+        }
+      }
+    }
+
+    // Markup like pauses is not read.
+    let speakable = segments::split(&message.text, self.config.max_pause)
+      .iter()
+      .any(|segment| segment.text.chars().any(char::is_alphanumeric));
+
+    if !speakable {
+      let script_stripped = message
+        .warnings
+        .iter()
+        .any(|warning| matches!(warning, SynthesisWarning::ScriptStripped { .. }));
+
+      if script_stripped && original.chars().any(char::is_alphabetic) {
+        return Err(TtsError::UnsupportedScript);
+      }
+
+      return Err(TtsError::NoSpeakableContent);
+    }
+
+    Ok(())
+  }
+
+  /// Preprocesses the message and wraps it with the configured prefix and suffix.
+  /// This is the only place where the prefix and suffix are added, every way
+  /// of creating audio must go through it exactly once.
+  fn prepare_text(
+    &self,
+    text: &str,
+    voice: &Voice,
+  ) -> Result<normalization::Preprocessed, TtsError> {
+    let message = self.preprocess_message(text, voice)?;
+
+    let mut pieces = Vec::with_capacity(3);
+
+    if let Some(prefix) = &self.config.message_prefix {
+      pieces.push(normalization::preprocess_fixed_text(
+        prefix,
+        &self.config,
+        &voice.voice,
+      ));
+    }
+
+    pieces.push(message.text);
+
+    if let Some(suffix) = &self.config.message_suffix {
+      pieces.push(normalization::preprocess_fixed_text(
+        suffix,
+        &self.config,
+        &voice.voice,
+      ));
+    }
+
+    Ok(normalization::Preprocessed {
+      text: pieces
+        .into_iter()
+        .filter(|piece| !piece.is_empty())
+        .collect::<Vec<_>>()
+        .join(" "),
+      warnings: message.warnings,
+    })
+  }
+
+  /// Preprocesses the message, without the prefix and suffix, and rejects it when it should not be read.
+  fn preprocess_message(
+    &self,
+    text: &str,
+    voice: &Voice,
+  ) -> Result<normalization::Preprocessed, TtsError> {
+    let text = match self.config.markup_policy {
+      MarkupPolicy::Apply => text.to_string(),
+      MarkupPolicy::Remove => segments::strip_markup(text),
+    };
+
+    let message = normalization::preprocess(&text, &self.config, &voice.voice);
+
+    self.check_message(&text, &message)?;
+
+    Ok(message)
+  }
+
+  /// Creates audio announcing that `donor` donated `amount` reais, written like "10,05",
+  /// followed by the donation message.
+  #[tracing::instrument(skip_all)]
+  pub async fn create_donation_audio(
+    &self,
+    donor: &str,
+    amount: &str,
+    message: &str,
+  ) -> Result<Vec<String>> {
+    let text = self.donation_text(donor, amount, message);
+
+    Ok(
+      self
+        .create_audio_detailed(text, CallOptions::default())
+        .await?
+        .urls,
+    )
+  }
+
+  /// Same as `create_donation_audio` but starts with `Config::first_donation_intro`,
+  /// for donors donating for the first time.
+  #[tracing::instrument(skip_all)]
+  pub async fn create_first_donation_audio(
+    &self,
+    donor: &str,
+    amount: &str,
+    message: &str,
+  ) -> Result<Vec<String>> {
+    let text = format!(
+      "{} {}",
+      self.config.first_donation_intro.replace("{donor}", donor),
+      self.donation_text(donor, amount, message)
+    );
+
+    Ok(
+      self
+        .create_audio_detailed(text, CallOptions::default())
+        .await?
+        .urls,
+    )
+  }
+
+  fn donation_text(&self, donor: &str, amount: &str, message: &str) -> String {
+    let amount = numbers::currency(amount, self.config.large_amounts)
+      .unwrap_or_else(|| format!("R$ {}", amount));
+
+    format!("{} doou {}. {}", donor, amount, message)
+  }
+
+  /// Reads `text` as a donation when it matches `Config::donation_template`
+  /// and as a plain message otherwise.
+  #[tracing::instrument(skip_all)]
+  pub async fn create_audio_from_input(&self, text: String) -> Result<Vec<String>> {
+    let donation = self
+      .config
+      .donation_template
+      .as_ref()
+      .and_then(|template| template.parse(&text));
+
+    match donation {
+      None => Ok(
+        self
+          .create_audio_detailed(text, CallOptions::default())
+          .await?
+          .urls,
+      ),
+      Some(donation) => {
+        self
+          .create_donation_audio(&donation.donor, &donation.amount, &donation.message)
+          .await
+      }
+    }
+  }
+
+  /// Same as `create_audio` but rejects the message with `TtsError::SenderRateLimited`
+  /// when `sender_id` already had `Config::sender_rate_limit` messages read recently.
+  /// Rejected messages don't count towards the limit. The message is read by the voice
+  /// `Config::sender_voices` assigns to the sender.
+  ///
+  /// Each message goes through these steps in order, the first one that rejects the
+  /// message decides the result:
+  /// 1. `Config::sender_rate_limit`, a message rejected by a later step still counts
+  ///    towards the limit.
+  /// 2. `Config::duplicate_window`.
+  /// 3. The cache, unless it is bypassed.
+  /// 4. The tts api.
+  #[tracing::instrument(skip_all, fields(sender_id = %sender_id, role = ?role))]
+  pub async fn create_audio_for_sender(
+    &self,
+    sender_id: &str,
+    role: SenderRole,
+    text: String,
+  ) -> Result<Vec<String>> {
+    self.check_sender_rate(sender_id, role, Instant::now())?;
+
+    Ok(
+      self
+        .create_audio_detailed(text, self.sender_options(sender_id))
+        .await?
+        .urls,
+    )
+  }
+
+  /// The options messages from `sender_id` are synthesized with, the voice
+  /// `Config::sender_voices` assigns to the sender.
+  pub fn sender_options(&self, sender_id: &str) -> CallOptions {
+    CallOptions {
+      voice: self
+        .config
+        .sender_voices
+        .as_ref()
+        .and_then(|voices| voices.voice_for(sender_id)),
+      ..CallOptions::default()
+    }
+  }
+
+  /// Creates and downloads an audio file containing a short phrase. Used to check that
+  /// the whole path from creating the audio to playing it works.
+  #[tracing::instrument(skip_all)]
+  pub async fn test_synthesis(&self) -> Result<AudioChunk> {
+    let url = self
+      .generate_audio(
+        String::from("teste de áudio"),
+        &self.voice_for(Tier::default()),
+      )
+      .await?;
+
+    let bytes = within_deadline(self.config.download_deadline, self.download(&url)).await?;
+
+    Ok(AudioChunk { url, bytes })
+  }
+
+  /// Same as `create_audio_merged` but also returns `resolution` peaks of the audio
+  /// to draw its waveform.
+  #[cfg(feature = "audio-analysis")]
+  #[tracing::instrument(skip_all, fields(tier = ?options.tier, resolution = %resolution))]
+  pub async fn create_audio_preview(
+    &self,
+    text: String,
+    options: CallOptions,
+    resolution: usize,
+  ) -> Result<AudioPreview> {
+    let audio = self.create_audio_merged(text, options).await?;
+
+    let envelope = {
+      let audio = audio.clone();
+      tokio::task::spawn_blocking(move || envelope::envelope(&audio, resolution))
+        .await
+        .context("envelope task failed")??
+    };
+
+    Ok(AudioPreview { audio, envelope })
+  }
+
+  /// Same as `create_audio_detailed` but downloads the audio files and joins them
+  /// into a single mp3 file. `Config::voice_break` of silence is added where the
+  /// voice changes and the pauses of the chunks are kept.
+  #[tracing::instrument(skip_all, fields(tier = ?options.tier))]
+  pub async fn create_audio_merged(&self, text: String, options: CallOptions) -> Result<Bytes> {
+    let chunks = self.create_chunk_details(text, options).await?;
+
+    let download_and_merge = async {
+      let files = futures::stream::iter(chunks.iter().map(|chunk| self.download(&chunk.url)))
+        .buffered(self.config.tuning().max_concurrency)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+      #[cfg(feature = "audio-analysis")]
+      let files = match self.config.loudness_target {
+        None => files,
+        Some(target) => tokio::task::spawn_blocking(move || {
+          files
+            .into_iter()
+            .map(|file| match loudness::normalize(&file, target) {
+              Ok(normalized) => Bytes::from(normalized),
+              Err(err) => {
+                error!(
+                  "unable to normalize chunk, merging it as is. error={:?}",
+                  err
+                );
+                file
+              }
+            })
+            .collect::<Vec<_>>()
+        })
+        .await
+        .context("loudness normalization task failed")?,
+      };
+
+      let parts: Vec<_> = chunks
+        .iter()
+        .zip(files.iter())
+        .map(|(chunk, bytes)| merge::Part {
+          bytes,
+          voice: &chunk.voice,
+          pause_after: chunk.pause_after,
+        })
+        .collect();
+
+      Ok::<_, anyhow::Error>(Bytes::from(merge::merge(
+        &parts,
+        self.config.voice_break,
+        self.config.voice_change_cue.as_deref(),
+      )))
+    };
+
+    within_deadline(self.config.download_deadline, download_and_merge).await
+  }
+
+  /// Same as `create_audio_merged` but decodes the audio and writes it to `buffer` as PCM
+  /// samples while the audio files are downloaded, so an audio device can start playing
+  /// the message before it's done. Waits while `buffer` is full. The format of the samples
+  /// is available from `PcmBuffer::format` once the first ones are written.
+  /// `Config::download_deadline` limits the time spent waiting for downloads, the time
+  /// spent waiting for space in `buffer` is not counted.
+  #[cfg(feature = "audio-analysis")]
+  #[tracing::instrument(skip_all, fields(tier = ?options.tier))]
+  pub async fn create_audio_pcm(
+    &self,
+    text: String,
+    options: CallOptions,
+    buffer: &PcmBuffer,
+  ) -> Result<PcmFormat> {
+    let chunks = self.create_chunk_details(text, options).await?;
+
+    let mut files = futures::stream::iter(chunks.iter().map(|chunk| self.download(&chunk.url)))
+      .buffered(self.config.tuning().max_concurrency);
+
+    let mut format = None;
+    // Only the time spent waiting for downloads counts towards the deadline,
+    // the time spent waiting for the reader of the buffer doesn't.
+    let mut spent = Duration::ZERO;
+
+    for (i, chunk) in chunks.iter().enumerate() {
+      let started_at = Instant::now();
+      let file = within_deadline(
+        self
+          .config
+          .download_deadline
+          .map(|deadline| deadline.saturating_sub(spent)),
+        async { files.next().await.transpose() },
+      )
+      .await?;
+      spent += started_at.elapsed();
+
+      let file = match file {
+        None => break,
+        Some(file) => file,
+      };
+
+      format = Some(pcm::decode_into(file.slice_ref(mp3::strip_id3(&file)), buffer).await?);
+
+      let voice_changes = chunks
+        .get(i + 1)
+        .is_some_and(|next| next.voice != chunk.voice);
+
+      let silence = if voice_changes {
+        chunk.pause_after.saturating_add(self.config.voice_break)
+      } else {
+        chunk.pause_after
+      };
+
+      pcm::write_silence(buffer, silence).await;
+    }
+
+    format.context("message has no audio")
+  }
+
+  /// Creates the audio of the message and returns how each chunk was created, in order.
+  async fn create_chunk_details(
+    &self,
+    text: String,
+    options: CallOptions,
+  ) -> Result<Vec<ChunkDetails>> {
+    let voice = self.message_voice(&options);
+
+    let details = self.create_audio_detailed(text, options).await?;
+
+    // The fallback audio has no chunk details.
+    let chunks = if details.chunks.is_empty() {
+      details
+        .urls
+        .into_iter()
+        .map(|url| ChunkDetails {
+          url,
+          voice: voice.clone(),
+          attempts: 0,
+          pause_after: Duration::ZERO,
+          rate: None,
+        })
+        .collect()
+    } else {
+      details.chunks
+    };
+
+    Ok(chunks)
+  }
+
+  /// Creates the audio of the message and returns a stream that downloads the audio
+  /// file of each chunk into `dir`, in order. The file of the next chunk is downloaded
+  /// when it is requested, plus the files of the `Config::prefetch_chunks` chunks after it
+  /// in the background so they are ready when requested. At most that many files plus
+  /// the current one are on disk when each `ChunkFile` is dropped before asking for the
+  /// next one, so long messages don't fill the disk. Dropping the stream stops the
+  /// downloads and deletes the files that were not returned. The stream stops at the
+  /// first download that fails. `Config::download_deadline` limits the time spent
+  /// waiting for downloads, the time the caller takes to handle each file is not counted.
+  #[tracing::instrument(skip_all, fields(tier = ?options.tier))]
+  pub async fn create_audio_files(
+    &self,
+    text: String,
+    options: CallOptions,
+    dir: &Path,
+  ) -> Result<impl Stream<Item = Result<ChunkFile>> + '_> {
+    let details = self.create_audio_detailed(text, options).await?;
+
+    let downloads = ChunkDownloads::new(details.urls, dir);
+
+    let prefetch = self
+      .config
+      .prefetch_chunks
+      .min(self.config.tuning().max_concurrency.saturating_sub(1));
+
+    let files = futures::stream::unfold(Some(downloads), move |downloads| async move {
+      let mut downloads = downloads?;
+
+      downloads.start(self, prefetch + 1);
+      let mut task = downloads.pending.pop_front()?;
+      // The next chunks are downloaded while the caller handles this one.
+      downloads.start(self, prefetch);
+
+      let started_at = Instant::now();
+      let file = within_deadline(
+        self
+          .config
+          .download_deadline
+          .map(|deadline| deadline.saturating_sub(downloads.spent)),
+        async {
+          (&mut task)
+            .await
+            .context("chunk download task failed")
+            .and_then(|file| file)
+        },
+      )
+      .await;
+      task.abort();
+      downloads.spent += started_at.elapsed();
+
+      // Nothing else is downloaded after a download fails.
+      let downloads = if file.is_ok() { Some(downloads) } else { None };
+
+      Some((file, downloads))
+    });
+
+    Ok(files)
+  }
+
+  fn voice_for(&self, tier: Tier) -> Voice {
+    self
+      .config
+      .tier_voices
+      .get(&tier)
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  /// The voice that reads the message, unless markup in the message changes it.
+  fn message_voice(&self, options: &CallOptions) -> Voice {
+    options
+      .voice
+      .clone()
+      .unwrap_or_else(|| self.voice_for(options.tier))
+  }
+
+  /// Returns the voices that will read `text` when it is synthesized with `options`,
+  /// in the order they are first used. A message is read in multiple voices when it
+  /// contains voice markup like "olá [voice=en-US]hello[/voice]" and
+  /// `Config::markup_policy` is `Apply`. Returns an empty list for messages that
+  /// would not be read.
+  pub fn planned_voices(&self, text: &str, options: &CallOptions) -> Vec<String> {
+    let voice = self.message_voice(options);
+
+    let chunks = match self
+      .prepare_text(text, &voice)
+      .map_err(anyhow::Error::from)
+      .and_then(|prepared| self.plan_chunks(&prepared.text, &voice))
+    {
+      Err(_) => return Vec::new(),
+      Ok(chunks) => chunks,
+    };
+
+    let mut voices: Vec<String> = Vec::new();
+
+    for chunk in chunks {
+      if !voices.contains(&chunk.voice.voice) {
+        voices.push(chunk.voice.voice);
+      }
+    }
+
+    voices
+  }
+
+  /// Splits the text into the pieces sent to the tts api, each with the voice that reads it.
+  /// Pieces without voice markup are read by `default_voice`.
+  fn plan_chunks(&self, text: &str, default_voice: &Voice) -> Result<Vec<PlannedChunk>> {
+    let mut planned = Vec::new();
+
+    for segment in segments::split(text, self.config.max_pause) {
+      let voice = match segment.voice {
+        None => default_voice.clone(),
+        Some(voice) => Voice {
+          engine: default_voice.engine.clone(),
+          voice,
+        },
+      };
+
+      planned.extend(self.chunk_segment(&segment.text, &voice, segment.pause_after)?);
+    }
+
+    Ok(self.arrange_chunks(planned))
+  }
+
+  /// Preprocesses and checks each `(text, voice)` piece like a message and divides it
+  /// into chunks read by its voice. Returns the length of the pieces as written, the
+  /// preprocessed pieces joined and the chunks. Pieces without text are ignored.
+  fn plan_pieces(
+    &self,
+    pieces: Vec<(String, String)>,
+    default_voice: &Voice,
+  ) -> Result<(usize, normalization::Preprocessed, Vec<PlannedChunk>)> {
+    let mut original_len = 0;
+    let mut texts = Vec::new();
+    let mut warnings = Vec::new();
+    let mut planned = Vec::new();
+
+    for (text, voice) in pieces {
+      if text.trim().is_empty() {
+        continue;
+      }
+
+      let voice = Voice {
+        engine: default_voice.engine.clone(),
+        voice,
+      };
+
+      // Markup would change the voice chosen by the caller.
+      let message = self.preprocess_message(&segments::strip_markup(&text), &voice)?;
+
+      // Preprocessing turns runs of punctuation into pauses.
+      for segment in segments::split(&message.text, self.config.max_pause) {
+        planned.extend(self.chunk_segment(&segment.text, &voice, segment.pause_after)?);
+      }
+
+      original_len += text.chars().count();
+      texts.push(message.text);
+      warnings.extend(message.warnings);
+    }
+
+    if texts.is_empty() {
+      return Err(TtsError::EmptyMessage.into());
+    }
+
+    let prepared = normalization::Preprocessed {
+      text: texts.join(" "),
+      warnings,
+    };
+
+    Ok((original_len, prepared, self.arrange_chunks(planned)))
+  }
+
+  /// Puts the chunks in the order of `Config::chunk_order` and sets their speaking rate.
+  fn arrange_chunks(&self, planned: Vec<PlannedChunk>) -> Vec<PlannedChunk> {
+    let texts: Vec<String> = planned.iter().map(|chunk| chunk.text.clone()).collect();
+    let mut slots: Vec<Option<PlannedChunk>> = planned.into_iter().map(Some).collect();
+    // SAFETY: the order has every index exactly once.
+    let mut planned: Vec<PlannedChunk> = self
+      .config
+      .chunk_order
+      .order(&texts)
+      .into_iter()
+      .map(|i| slots[i].take().unwrap())
+      .collect();
+
+    if let Some(speed_ramp) = &self.config.speed_ramp {
+      let rates = speed_ramp.rates(planned.len());
+      for (chunk, rate) in planned.iter_mut().zip(rates) {
+        chunk.rate = rate;
+      }
+    }
+
+    planned
+  }
+
+  /// Divides the text of a segment into chunks read by `voice`.
+  /// `pause_after` is added after the last chunk.
+  fn chunk_segment(
+    &self,
+    text: &str,
+    voice: &Voice,
+    pause_after: Duration,
+  ) -> Result<Vec<PlannedChunk>> {
+    let mut segment_chunks = chunks::divide_text_into_chunks(
+      text,
+      self.config.max_chunk_len,
+      self.config.length_unit,
+      &self.config.break_finder,
+    )?;
+
+    chunks::merge_short_last_chunk(
+      &mut segment_chunks,
+      self.config.min_chunk_len,
+      self.config.max_chunk_len,
+      self.config.chunk_len_tolerance,
+      self.config.length_unit,
+    );
+
+    let mut planned: Vec<PlannedChunk> = segment_chunks
+      .into_iter()
+      .map(|chunk| PlannedChunk {
+        text: chunk,
+        voice: voice.clone(),
+        pause_after: Duration::ZERO,
+        rate: None,
+      })
+      .collect();
+
+    // The pause comes after the whole segment is read.
+    if let Some(last) = planned.last_mut() {
+      last.pause_after = pause_after;
+    }
+
+    Ok(planned)
+  }
+
+  /// Creates audio for pieces of text divided by the caller, each `(text, voice)` read
+  /// by its voice, and returns the urls of the audio files in order. Each piece is
+  /// preprocessed, checked and divided in chunks like a message, the message prefix
+  /// and suffix are not added. Voice markup in the text is ignored.
+  #[tracing::instrument(skip_all, fields(segments = %segments.len()))]
+  pub async fn create_audio_segments(
+    &self,
+    segments: Vec<(String, String)>,
+  ) -> Result<Vec<String>> {
+    Ok(
+      self
+        .synthesize(
+          MessageInput::Segments(segments),
+          CallOptions::default(),
+          None,
+        )
+        .await?
+        .urls,
+    )
+  }
+
+  /// Same as `create_audio` but also reports the transformations
+  /// that were applied to the message before it was read.
+  #[tracing::instrument(skip_all, fields(tier = ?options.tier))]
+  pub async fn create_audio_detailed(
+    &self,
+    text: String,
+    options: CallOptions,
+  ) -> Result<SynthesisDetails> {
+    self
+      .synthesize(MessageInput::Text(text), options, None)
+      .await
+  }
+
+  /// Same as `create_audio` but also returns a channel where the progress of the
+  /// synthesis is sent. The channel is closed when the returned future completes.
+  /// The synthesis waits for the receiver when the channel is full, so the receiver
+  /// should be read at the same time the future is awaited, or dropped.
+  pub fn create_audio_with_progress(
+    &self,
+    text: String,
+  ) -> (
+    impl std::future::Future<Output = Result<Vec<String>>> + '_,
+    Receiver<SynthesisProgress>,
+  ) {
+    let (sender, receiver) = mpsc::channel(PROGRESS_CHANNEL_CAPACITY);
+
+    let future = async move {
+      let result = self
+        .synthesize(
+          MessageInput::Text(text),
+          CallOptions::default(),
+          Some(&sender),
+        )
+        .await;
+
+      if result.is_ok() {
+        // The receiver may have been dropped.
+        let _ = sender.send(SynthesisProgress::Finished).await;
+      }
+
+      result.map(|details| details.urls)
+    };
+
+    (future, receiver)
+  }
+
+  /// Creates the audio of the message, unless it is cancelled by its correlation id.
+  async fn synthesize(
+    &self,
+    input: MessageInput,
+    options: CallOptions,
+    progress: Option<&Sender<SynthesisProgress>>,
+  ) -> Result<SynthesisDetails> {
+    let correlation_id = match &options.correlation_id {
+      None => return self.synthesize_message(input, options, progress).await,
+      Some(correlation_id) => correlation_id.clone(),
+    };
+
+    let (handle, registration) = AbortHandle::new_pair();
+    let _guard = self.track_operation(correlation_id, handle);
+
+    match Abortable::new(
+      self.synthesize_message(input, options, progress),
+      registration,
+    )
+    .await
+    {
+      Ok(result) => result,
+      Err(_) => Err(TtsError::Cancelled.into()),
+    }
+  }
+
+  async fn synthesize_message(
+    &self,
+    input: MessageInput,
+    options: CallOptions,
+    progress: Option<&Sender<SynthesisProgress>>,
+  ) -> Result<SynthesisDetails> {
+    if let Some(sample_rate) = self.config.sample_rate {
+      if !contracts::tts::TextToSpeech::capabilities(self)
+        .sample_rates
+        .contains(&sample_rate)
+      {
+        return Err(TtsError::UnsupportedSampleRate(sample_rate).into());
+      }
+    }
+
+    if self.config.speed_ramp.is_some()
+      && !contracts::tts::TextToSpeech::capabilities(self).rate_control
+    {
+      return Err(TtsError::RateControlUnsupported.into());
+    }
+
+    let voice = self.message_voice(&options);
+
+    let (original_len, prepared, chunks) = match input {
+      MessageInput::Text(text) => {
+        let prepared = self.prepare_text(&text, &voice)?;
+        let chunks = self.plan_chunks(&prepared.text, &voice)?;
+        (text.chars().count(), prepared, chunks)
+      }
+      MessageInput::Segments(pieces) => self.plan_pieces(pieces, &voice)?,
+    };
+
+    // Before the cache, a duplicate is not read again even when it costs nothing.
+    let duplicate = self.check_duplicate(&prepared.text, Instant::now())?;
+
+    if !prepared.warnings.is_empty() {
+      info!(
+        "message changed by preprocessing. warnings={:?}",
+        &prepared.warnings
+      );
+    }
+
+    let filtered = self.filter_chunks(&chunks)?;
+    let chunks: Vec<(PlannedChunk, ChunkFilter)> = chunks
+      .into_iter()
+      .zip(filtered.iter().copied())
+      .filter(|(_, filter)| *filter != ChunkFilter::Skipped)
+      .collect();
+
+    self
+      .stats
+      .record(original_len, prepared.text.chars().count(), chunks.len());
+
+    info!(
+      "divided text in chunks. chunks={:?}",
+      chunks
+        .iter()
+        .map(|(chunk, filter)| (self.loggable_text(&chunk.text), &chunk.voice.voice, filter))
+        .collect::<Vec<_>>()
+    );
+
+    if let Some(progress) = progress {
+      let _ = progress
+        .send(SynthesisProgress::Started {
+          chunks: chunks.len(),
+        })
+        .await;
+    }
+
+    // Identical chunks, like a phrase repeated in the message, are synthesized once
+    // and the audio is used in every position they appear in.
+    let pauses: Vec<Duration> = chunks.iter().map(|(chunk, _)| chunk.pause_after).collect();
+    let mut unique: Vec<(PlannedChunk, Vec<usize>)> = Vec::new();
+    let mut bleeps: Vec<(usize, ChunkDetails)> = Vec::new();
+    for (index, (chunk, filter)) in chunks.into_iter().enumerate() {
+      if filter == ChunkFilter::Bleeped {
+        // SAFETY: chunks are only bleeped when there's a bleep audio.
+        let url = self.config.bleep_audio.clone().unwrap();
+
+        if let Some(progress) = progress {
+          let _ = progress
+            .send(SynthesisProgress::ChunkCreated {
+              index,
+              url: url.clone(),
+            })
+            .await;
+        }
+
+        bleeps.push((
+          index,
+          ChunkDetails {
+            url,
+            voice: chunk.voice,
+            attempts: 0,
+            pause_after: chunk.pause_after,
+            rate: None,
+          },
+        ));
+        continue;
+      }
+
+      match unique.iter_mut().find(|(other, _)| {
+        other.text == chunk.text && other.voice == chunk.voice && other.rate == chunk.rate
+      }) {
+        Some((_, indexes)) => indexes.push(index),
+        None => unique.push((chunk, vec![index])),
+      }
+    }
+
+    let options = &options;
+    let chunks = futures::stream::iter(unique.into_iter().map(|(chunk, indexes)| async move {
+      self
+        .synthesize_planned_chunk(&indexes, chunk, options, progress)
+        .await
+        .map(|details| (indexes, details))
+    }))
+    .buffered(self.config.tuning().max_concurrency)
+    .collect::<Vec<_>>()
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>>>()
+    .map(|unique| {
+      let mut chunks: Vec<Option<ChunkDetails>> = vec![None; pauses.len()];
+      for (index, details) in bleeps {
+        chunks[index] = Some(details);
+      }
+      for (indexes, details) in unique {
+        for index in indexes {
+          chunks[index] = Some(ChunkDetails {
+            pause_after: pauses[index],
+            ..details.clone()
+          });
+        }
+      }
+      // Every index belongs to one of the unique chunks or is bleeped.
+      chunks.into_iter().flatten().collect::<Vec<_>>()
+    });
+
+    let chunks = match (chunks, &self.config.fallback_audio) {
+      (Ok(chunks), _) => chunks,
+      (Err(err), None) => return Err(err),
+      (Err(err), Some(fallback_audio)) => {
+        error!(
+          "error creating audio, returning fallback audio. error={:?}",
+          err
+        );
+
+        duplicate.commit();
+
+        let mut warnings = prepared.warnings;
+        warnings.push(SynthesisWarning::FallbackAudio);
+
+        return Ok(SynthesisDetails {
+          urls: vec![fallback_audio.clone()],
+          chunks: Vec::new(),
+          warnings,
+          filtered: Vec::new(),
+        });
+      }
+    };
+
+    duplicate.commit();
+
+    Ok(SynthesisDetails {
+      urls: chunks.iter().map(|chunk| chunk.url.clone()).collect(),
+      chunks,
+      warnings: prepared.warnings,
+      filtered,
+    })
+  }
+
+  /// Decides what is done with each chunk according to `Config::blocked_word_policy`.
+  /// Fails when no chunk would be read.
+  fn filter_chunks(&self, chunks: &[PlannedChunk]) -> Result<Vec<ChunkFilter>, TtsError> {
+    let flagged = match self.config.blocked_word_policy {
+      BlockedWordPolicy::Remove | BlockedWordPolicy::Reject => ChunkFilter::Allowed,
+      BlockedWordPolicy::BleepChunk if self.config.bleep_audio.is_some() => ChunkFilter::Bleeped,
+      BlockedWordPolicy::SkipChunk | BlockedWordPolicy::BleepChunk => ChunkFilter::Skipped,
+    };
+
+    let mut blocked_word = None;
+
+    let filtered: Vec<ChunkFilter> = chunks
+      .iter()
+      .map(|chunk| {
+        match normalization::find_blocked_word(
+          &chunk.text,
+          &self.config.blocked_words,
+          self.config.match_leetspeak,
+        ) {
+          None => ChunkFilter::Allowed,
+          Some(word) => {
+            blocked_word.get_or_insert_with(|| word.to_string());
+            flagged
+          }
+        }
+      })
+      .collect();
+
+    if let Some(word) = blocked_word {
+      if filtered
+        .iter()
+        .all(|filter| *filter == ChunkFilter::Skipped)
+      {
+        return Err(TtsError::BlockedWord(word));
+      }
+    }
+
+    Ok(filtered)
+  }
+
+  /// Creates the audio of the chunk found at `indexes` and reports each of them to `progress`.
+  async fn synthesize_planned_chunk(
+    &self,
+    indexes: &[usize],
+    chunk: PlannedChunk,
+    options: &CallOptions,
+    progress: Option<&Sender<SynthesisProgress>>,
+  ) -> Result<ChunkDetails> {
+    let details = self
+      .synthesize_chunk(chunk.text, &chunk.voice, chunk.rate, options)
+      .await?;
+
+    if let Some(progress) = progress {
+      for index in indexes {
+        let _ = progress
+          .send(SynthesisProgress::ChunkCreated {
+            index: *index,
+            url: details.url.clone(),
+          })
+          .await;
+      }
+    }
+
+    Ok(ChunkDetails {
+      pause_after: chunk.pause_after,
+      rate: chunk.rate,
+      ..details
+    })
+  }
+
+  /// Creates audio with `voice` and then with each fallback voice until one of them succeeds.
+  async fn generate_audio_with_fallbacks(
+    &self,
+    text: String,
+    voice: &Voice,
+    rate: Option<u32>,
+  ) -> Result<ChunkDetails> {
+    let mut attempts = 0;
+    let mut last_error = None;
+
+    for voice in std::iter::once(voice).chain(self.config.fallback_voices.iter()) {
+      match self
+        .generate_audio_with_retries(text.clone(), voice, &mut attempts)
+        .await
+      {
+        Ok(url) => {
+          return Ok(ChunkDetails {
+            url,
+            voice: voice.clone(),
+            attempts,
+            pause_after: Duration::ZERO,
+            rate,
+          })
+        }
+        Err(err) => {
+          error!(
+            "error generating audio with voice. voice={:?} error={:?}",
+            voice, err
+          );
+          last_error = Some(err);
+        }
+      }
+    }
+
+    // SAFETY: there's always at least one voice.
+    Err(last_error.unwrap()).with_context(|| format!("every voice failed. attempts={}", attempts))
+  }
+
+  /// Calls `generate_audio` retrying according to the configured tuning.
+  /// `attempts` is incremented for every request made.
+  async fn generate_audio_with_retries(
+    &self,
+    text: String,
+    voice: &Voice,
+    attempts: &mut u32,
+  ) -> Result<String> {
+    let tuning = self.config.tuning();
+
+    let mut attempt = 0;
+    let mut previous_delay = Duration::ZERO;
+    let mut refreshed_credentials = false;
+
+    loop {
+      *attempts += 1;
+      let generation = self.credentials.lock().unwrap().generation;
+      match self.generate_audio(text.clone(), voice).await {
+        Ok(url) => return Ok(url),
+        Err(err) if err.downcast_ref::<TtsError>() == Some(&TtsError::InvalidCredentials) => {
+          return Err(err)
+        }
+        Err(err) if err.downcast_ref::<TtsError>() == Some(&TtsError::CredentialsExpired) => {
+          let refresher = match &self.config.refresh_credentials {
+            None => return Err(err),
+            Some(refresher) => refresher,
+          };
+
+          // Credentials that are rejected right after being refreshed are not valid.
+          if refreshed_credentials {
+            return Err(err).context(TtsError::InvalidCredentials);
+          }
+
+          error!("credentials expired, refreshing them. error={:?}", err);
+          self.refresh_credentials(refresher, generation).await?;
+          refreshed_credentials = true;
+        }
+        Err(err) if attempt < tuning.retries => {
+          let delay =
+            tuning
+              .backoff
+              .jittered_delay(attempt, previous_delay, &mut rand::thread_rng());
+          previous_delay = delay;
+          error!(
+            "error generating audio, will retry. attempt={} delay={:?} error={:?}",
+            attempt, delay, err
+          );
+          tokio::time::sleep(delay).await;
+          attempt += 1;
+        }
+        Err(err) => return Err(err),
+      }
+    }
+  }
+
+  /// Returns `text` the way it should appear in the logs.
+  fn loggable_text(&self, text: &str) -> String {
+    if self.config.redact_logs {
+      redact(text)
+    } else {
+      text.to_string()
+    }
+  }
+
+  #[tracing::instrument(skip_all, fields(text = %self.loggable_text(&text), voice = ?voice))]
+  async fn generate_audio(&self, text: String, voice: &Voice) -> Result<String> {
+    // Held until the audio file is ready.
+    let _voice_permit = match self.voice_limits.get(&voice.voice) {
+      None => None,
+      Some(limit) => Some(limit.acquire().await?),
+    };
+
+    let body = CreateSoundRequest {
+      engine: voice.engine.clone(),
+      data: CreateSoundRequestData {
+        text,
+        voice: voice.voice.clone(),
+        // Checked against the capabilities before the message is synthesized.
+        sample_rate: self.config.sample_rate,
+      },
+    };
+
+    let loggable_body = format!(
+      "engine={} voice={} text={}",
+      &body.engine,
+      &body.data.voice,
+      self.loggable_text(&body.data.text)
+    );
+
+    let url = "https://api.soundoftext.com/sounds";
+
+    let response = self
+      .http_client
+      .post(
+        url,
+        serde_json::to_vec(&body)?,
+        Some(PostOptions {
+          headers: Some(self.request_headers()),
+          timeout: None,
+        }),
+      )
+      .await;
+
+    self.capture_exchange(
+      "POST",
+      url,
+      Some(&loggable_body),
+      response
+        .as_ref()
+        .map(|response| (response.status, &response.body[..])),
+    );
+
+    let response = response.with_context(|| format!("request_body={}", &loggable_body))?;
+
+    check_auth_status(response.status)
+      .with_context(|| format!("request_body={}", &loggable_body))?;
+
+    if is_blank(&response.body) {
+      return Err(TtsError::EmptyResponse)
+        .with_context(|| format!("request_body={}", &loggable_body));
+    }
+
+    let response = serde_json::from_slice::<CreateSoundResponse>(&response.body)
+      .with_context(|| format!("request_body={}", &loggable_body))?;
+
+    info!("created audio file. response={:?}", &response);
+
+    // Held until the audio file is ready.
+    let _permit = self.polls.acquire().await?;
+
+    let url = format!("https://api.soundoftext.com/sounds/{}", response.id);
+
+    loop {
+      let response = self
+        .http_client
+        .get(
+          &url,
+          Some(GetOptions {
+            headers: Some(self.request_headers()),
+            query: None,
+            timeout: Some(Duration::from_secs(60)),
+          }),
+        )
+        .await;
+
+      self.capture_exchange(
+        "GET",
+        &url,
+        None,
+        response
+          .as_ref()
+          .map(|response| (response.status, &response.body[..])),
+      );
+
+      let response = response?;
+
+      check_auth_status(response.status)
+        .with_context(|| format!("polling audio file. request_body={}", &loggable_body))?;
+
+      if is_blank(&response.body) {
+        return Err(TtsError::EmptyResponse)
+          .with_context(|| format!("polling audio file. request_body={}", &loggable_body));
+      }
+
+      let response_body_text = String::from_utf8_lossy(&response.body);
+
+      match serde_json::from_str::<GetSoundLocationResponse>(&response_body_text) {
+        Err(err) => {
+          let error = Err(anyhow::anyhow!(
+            "unexpected tts response. request_body={}, response={:?} error={:?}",
+            &loggable_body,
+            response_body_text,
+            err
+          ));
+          error!("error={:?}", error);
+          return error;
+        }
+        Ok(data) => {
+          if data.status != "Pending" {
+            info!("requested audio file location. response_body={:?}", &data);
+            // SAFETY: location should be filled when status is not Pending.
+            return Ok(data.location.unwrap());
+          }
+
+          info!("audio file is not ready, will try again after delay");
+          tokio::time::sleep(self.config.tuning().poll_interval).await;
+        }
+      }
+    }
+  }
+}
+
+impl Drop for Tts {
+  fn drop(&mut self) {
+    // Tasks that were not awaited by `shutdown` are cancelled.
+    if let Ok(tasks) = self.background_tasks.get_mut() {
+      for task in tasks.drain(..) {
+        task.abort();
+      }
+    }
+  }
+}
+
+/// Hides `text` while keeping enough information to tell messages apart in the logs.
+fn redact(text: &str) -> String {
+  let mut hasher = DefaultHasher::new();
+  text.hash(&mut hasher);
+
+  format!(
+    "<redacted len={} hash={:016x}>",
+    text.chars().count(),
+    hasher.finish()
+  )
+}
+
+/// Whether a response body has nothing but whitespace.
+fn is_blank(body: &[u8]) -> bool {
+  body.iter().all(u8::is_ascii_whitespace)
+}
+
+#[async_trait]
+impl contracts::tts::TextToSpeech for Tts {
+  /// Creates a mp3 file containing `text` and returns its url.
+  #[tracing::instrument(skip_all)]
+  async fn create_audio(&self, text: String) -> Result<Vec<String>> {
+    self.create_audio_from_input(text).await
+  }
+
+  fn capabilities(&self) -> TtsCapabilities {
+    TtsCapabilities {
+      ssml: false,
+      speech_marks: false,
+      // soundoftext reads every message at the normal rate.
+      rate_control: false,
+      formats: vec!["mp3"],
+      // Longer messages are split in chunks, they only fail when they are rejected.
+      max_input_len: match self.config.overflow_policy {
+        OverflowPolicy::Truncate => None,
+        OverflowPolicy::Reject => self.config.max_message_len,
+      },
+      sample_rates: self.config.api_sample_rates.clone(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header::HeaderMap;
+
+  use crate::contracts::{
+    cache::MockCache,
+    http::{GetResponse, MockHttpClient, PostResponse},
+    tts::TextToSpeech,
+  };
+
+  use super::{cache::audio_url_cache_key, *};
+
+  /// The start of a mp3 file: an ID3 tag header followed by a mpeg audio frame header.
+  const MP3_FILE: &[u8] = &[
+    b'I', b'D', b'3', 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFB, 0x90, 0x64,
+  ];
+
+  /// Returns a http client that records the requests sent to the tts api
+  /// and answers every request with an audio file that is ready.
+  fn mock_http_client(requests: Arc<Mutex<Vec<serde_json::Value>>>) -> MockHttpClient {
+    mock_http_client_failing_for(requests, &[])
+  }
+
+  /// Same as `mock_http_client` but requests to create audio with
+  /// one of `failing_voices` fail.
+  fn mock_http_client_failing_for(
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    failing_voices: &[&str],
+  ) -> MockHttpClient {
+    let failing_voices: Vec<String> = failing_voices
+      .iter()
+      .map(|voice| voice.to_string())
+      .collect();
+
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(move |_, body, _| {
+      let request: serde_json::Value = serde_json::from_slice(&body)?;
+      let voice = request["data"]["voice"].as_str().unwrap().to_string();
+
+      requests.lock().unwrap().push(request);
+
+      if failing_voices.contains(&voice) {
+        return Err(anyhow::anyhow!("service unavailable"));
+      }
+
+      Ok(PostResponse {
+        status: 200,
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "id": "sound_id"
+        }))?),
+      })
+    });
+
+    http_client.expect_get().returning(|url, _| {
+      if url.ends_with(".mp3") {
+        return Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from_static(MP3_FILE),
+        });
+      }
+
+      Ok(GetResponse {
+        status: 200,
+        headers: HeaderMap::new(),
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "status": "Done",
+          "location": "https://files.soundoftext.com/sound_id.mp3"
+        }))?),
+      })
+    });
+
+    http_client
+  }
+
+  /// Returns the text of each request sent to the tts api.
+  fn texts(requests: &Mutex<Vec<serde_json::Value>>) -> Vec<String> {
+    requests
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|request| request["data"]["text"].as_str().unwrap().to_string())
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn wraps_message_with_prefix_and_suffix() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        message_prefix: Some(String::from("  Mensagem:")),
+        message_suffix: Some(String::from("plim  plim ")),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let urls = tts.create_audio(String::from("olá   pessoal")).await?;
+
+    assert_eq!(vec!["https://files.soundoftext.com/sound_id.mp3"], urls);
+    assert_eq!(
+      vec![String::from("Mensagem: olá pessoal plim plim")],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn chunks_of_messages_with_emotes_and_emoji_spam_keep_their_words(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let tts = Tts::new(Config::default(), Arc::new(MockHttpClient::new()), None);
+
+    let tests = vec![
+      ("PogChamp obrigado!!! 🎉🎉🎉", "obrigado!"),
+      ("Kappa valeu 🔥🔥🔥??", "valeu fogo?"),
+    ];
+
+    for (input, expected) in tests {
+      let prepared = tts.prepare_text(input, &Voice::default())?;
+      let chunks: Vec<String> = tts
+        .plan_chunks(&prepared.text, &Voice::default())?
+        .into_iter()
+        .map(|chunk| chunk.text)
+        .collect();
+      assert_eq!(vec![expected.to_string()], chunks, "input={}", input);
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn auto_number_locale_reads_numbers_the_way_the_voice_writes_them(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        number_locale: Some(NumberLocale::Auto),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts
+      .create_audio_detailed(
+        String::from("1,234.56"),
+        CallOptions {
+          voice: Some(Voice {
+            engine: String::from("google"),
+            voice: String::from("en-US"),
+          }),
+          ..CallOptions::default()
+        },
+      )
+      .await?;
+
+    tts
+      .create_audio_detailed(String::from("1.234,56"), CallOptions::default())
+      .await?;
+
+    assert_eq!(
+      vec![
+        String::from("mil duzentos e trinta e quatro vírgula cinquenta e seis"),
+        String::from("mil duzentos e trinta e quatro vírgula cinquenta e seis"),
+      ],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn prefix_and_suffix_are_not_filtered_like_messages(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        message_prefix: Some(String::from("!nova mensagem. Leia com calma:")),
+        message_suffix: Some(String::from("Fim. Tchau!")),
+        command_prefixes: vec![String::from("!")],
+        max_sentences: Some(1),
+        blocked_words: vec![String::from("calma")],
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts.create_audio(String::from("olá pessoal")).await?;
+
+    assert_eq!(
+      vec![String::from(
+        "!nova mensagem. Leia com calma: olá pessoal Fim. Tchau!"
+      )],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reports_warnings_for_messages_changed_by_preprocessing(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_message_len: Some(20),
+        blocked_words: vec![String::from("porra")],
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("porra, que live boa demais, valeu pela live"),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(
+      vec![
+        SynthesisWarning::WordsFiltered { count: 1 },
+        SynthesisWarning::Truncated {
+          original_len: 36,
+          len: 13
+        },
+      ],
+      details.warnings
+    );
+    assert_eq!(vec![String::from("que live boa…")], texts(&requests));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn uses_the_voice_configured_for_the_tier() -> Result<(), Box<dyn std::error::Error>> {
+    let tier_voices = HashMap::from([
+      (
+        Tier::Fast,
+        Voice {
+          engine: String::from("google"),
+          voice: String::from("pt-PT"),
+        },
+      ),
+      (
+        Tier::Balanced,
+        Voice {
+          engine: String::from("google"),
+          voice: String::from("pt-BR"),
+        },
+      ),
+      (
+        Tier::HighQuality,
+        Voice {
+          engine: String::from("premium"),
+          voice: String::from("pt-BR-Neural"),
+        },
+      ),
+    ]);
+
+    for tier in [Tier::Fast, Tier::Balanced, Tier::HighQuality] {
+      let requests = Arc::new(Mutex::new(Vec::new()));
+
+      let tts = Tts::new(
+        Config {
+          tier_voices: tier_voices.clone(),
+          ..Config::default()
+        },
+        Arc::new(mock_http_client(Arc::clone(&requests))),
+        None,
+      );
+
+      tts
+        .create_audio_detailed(
+          String::from("olá"),
+          CallOptions {
+            tier,
+            ..CallOptions::default()
+          },
+        )
+        .await?;
+
+      let expected = &tier_voices[&tier];
+      let requests = requests.lock().unwrap();
+      assert_eq!(1, requests.len(), "tier={:?}", tier);
+      assert_eq!(expected.engine, requests[0]["engine"], "tier={:?}", tier);
+      assert_eq!(
+        expected.voice, requests[0]["data"]["voice"],
+        "tier={:?}",
+        tier
+      );
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn shutdown_waits_for_pending_cache_writes() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let writes = Arc::new(Mutex::new(Vec::new()));
+
+    let mut cache = MockCache::new();
+    cache.expect_get().returning(|_| Ok(None));
+    {
+      let writes = Arc::clone(&writes);
+      cache.expect_put().returning(move |key, value, _| {
+        writes.lock().unwrap().push((key, value));
+        Ok(())
+      });
+    }
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      Some(Arc::new(cache)),
+    );
+
+    tts.create_audio(String::from("olá")).await?;
+
+    tts.shutdown().await;
+
+    assert!(tts.background_tasks.lock().unwrap().is_empty());
+    assert_eq!(
+      vec![(
+        audio_url_cache_key("olá", &Voice::default(), None),
+        b"https://files.soundoftext.com/sound_id.mp3".to_vec()
+      )],
+      *writes.lock().unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn dropping_without_shutdown_does_not_panic() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cache = MockCache::new();
+    cache.expect_get().returning(|_| Ok(None));
+    cache.expect_put().returning(|_, _, _| Ok(()));
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::new(Mutex::new(Vec::new())))),
+      Some(Arc::new(cache)),
+    );
+
+    tts.create_audio(String::from("olá")).await?;
+
+    drop(tts);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reads_audio_url_from_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let mut cache = MockCache::new();
+    cache
+      .expect_get()
+      .returning(|_| Ok(Some(b"https://files.soundoftext.com/cached.mp3".to_vec())));
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      Some(Arc::new(cache)),
+    );
+
+    let urls = tts.create_audio(String::from("olá")).await?;
+
+    assert_eq!(vec!["https://files.soundoftext.com/cached.mp3"], urls);
+    assert!(requests.lock().unwrap().is_empty());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn duplicates_are_rejected_before_the_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let mut cache = MockCache::new();
+    cache.expect_get().returning(|key| {
+      if key == audio_url_cache_key("olá", &Voice::default(), None) {
+        Ok(Some(b"https://files.soundoftext.com/cached.mp3".to_vec()))
+      } else {
+        Ok(None)
+      }
+    });
+    cache.expect_put().returning(|_, _, _| Ok(()));
+
+    let tts = Tts::new(
+      Config {
+        duplicate_window: Some(Duration::from_secs(60)),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      Some(Arc::new(cache)),
+    );
+
+    let bypass_cache = CallOptions {
+      bypass_cache: true,
+      ..CallOptions::default()
+    };
+
+    let details = tts
+      .create_audio_detailed(String::from("olá"), CallOptions::default())
+      .await?;
+    assert_eq!(
+      vec!["https://files.soundoftext.com/cached.mp3"],
+      details.urls
+    );
+
+    // Cached and duplicate.
+    let err = tts
+      .create_audio_detailed(String::from("Olá!!"), CallOptions::default())
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&TtsError::DuplicateMessage),
+      err.downcast_ref::<TtsError>()
+    );
+
+    // Bypassing the cache and duplicate.
+    let err = tts
+      .create_audio_detailed(String::from("olá"), bypass_cache.clone())
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&TtsError::DuplicateMessage),
+      err.downcast_ref::<TtsError>()
+    );
+
+    tts
+      .create_audio_detailed(String::from("tchau"), bypass_cache)
+      .await?;
+
+    assert_eq!(vec!["tchau"], texts(&requests));
+
+    tts.shutdown().await;
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn the_sender_rate_limit_is_checked_before_duplicates(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        sender_rate_limit: Some(SenderRateLimit {
+          max_messages: 1,
+          window: Duration::from_secs(60),
+          exempt_senders: Vec::new(),
+        }),
+        duplicate_window: Some(Duration::from_secs(60)),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts
+      .create_audio_for_sender("viewer", SenderRole::Regular, String::from("oi"))
+      .await?;
+
+    for (sender_id, expected) in [
+      (
+        "viewer",
+        TtsError::SenderRateLimited(String::from("viewer")),
+      ),
+      ("fan", TtsError::DuplicateMessage),
+    ] {
+      let err = tts
+        .create_audio_for_sender(sender_id, SenderRole::Regular, String::from("oi"))
+        .await
+        .unwrap_err();
+      assert_eq!(Some(&expected), err.downcast_ref::<TtsError>());
+    }
+
+    // The duplicate counted towards the limit of its sender.
+    let err = tts
+      .create_audio_for_sender("fan", SenderRole::Regular, String::from("tchau"))
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&TtsError::SenderRateLimited(String::from("fan"))),
+      err.downcast_ref::<TtsError>()
+    );
+
+    assert_eq!(vec!["oi"], texts(&requests));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn messages_that_were_not_read_are_not_duplicates() -> Result<(), Box<dyn std::error::Error>>
+  {
+    // No chunk is left after filtering.
+    let tts = Tts::new(
+      Config {
+        duplicate_window: Some(Duration::from_secs(60)),
+        blocked_words: vec![String::from("porra")],
+        blocked_word_policy: BlockedWordPolicy::SkipChunk,
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    for _ in 0..2 {
+      let err = tts
+        .create_audio_detailed(String::from("que porra"), CallOptions::default())
+        .await
+        .unwrap_err();
+      assert_eq!(
+        Some(&TtsError::BlockedWord(String::from("porra"))),
+        err.downcast_ref::<TtsError>()
+      );
+    }
+
+    // The tts api fails.
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        duplicate_window: Some(Duration::from_secs(60)),
+        retries: Some(0),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_failing_for(
+        Arc::clone(&requests),
+        &["pt-BR"],
+      )),
+      None,
+    );
+
+    for _ in 0..2 {
+      let err = tts
+        .create_audio_detailed(String::from("olá"), CallOptions::default())
+        .await
+        .unwrap_err();
+      assert_ne!(
+        Some(&TtsError::DuplicateMessage),
+        err.downcast_ref::<TtsError>()
+      );
+    }
+
+    assert_eq!(vec!["olá", "olá"], texts(&requests));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn cancelled_messages_are_not_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    let ready = Arc::new(Mutex::new(false));
+    let polls = Arc::new(Mutex::new(0));
+
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(|_, _, _| {
+      Ok(PostResponse {
+        status: 200,
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "id": "sound_id"
+        }))?),
+      })
+    });
+
+    {
+      let ready = Arc::clone(&ready);
+      let polls = Arc::clone(&polls);
+      // The audio file is not ready until the first message is cancelled.
+      http_client.expect_get().returning(move |url, _| {
+        *polls.lock().unwrap() += 1;
+
+        if url.ends_with(".mp3") {
+          return Ok(GetResponse {
+            status: 200,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(MP3_FILE),
+          });
+        }
+
+        let body = if *ready.lock().unwrap() {
+          serde_json::json!({
+            "status": "Done",
+            "location": "https://files.soundoftext.com/sound_id.mp3"
+          })
+        } else {
+          serde_json::json!({ "status": "Pending" })
+        };
+
+        Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from(serde_json::to_vec(&body)?),
+        })
+      });
+    }
+
+    let tts = Arc::new(Tts::new(
+      Config {
+        poll_interval: Some(Duration::from_millis(5)),
+        duplicate_window: Some(Duration::from_secs(60)),
+        ..Config::default()
+      },
+      Arc::new(http_client),
+      None,
+    ));
+
+    let options = CallOptions {
+      correlation_id: Some(String::from("donation-1")),
+      ..CallOptions::default()
+    };
+
+    let synthesis = tokio::spawn({
+      let tts = Arc::clone(&tts);
+      let options = options.clone();
+      async move {
+        tts
+          .create_audio_detailed(String::from("obrigado pela doação"), options)
+          .await
+      }
+    });
+
+    while *polls.lock().unwrap() == 0 {
+      tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    assert!(tts.cancel("donation-1"));
+
+    let err = synthesis.await?.unwrap_err();
+    assert_eq!(Some(&TtsError::Cancelled), err.downcast_ref::<TtsError>());
+
+    *ready.lock().unwrap() = true;
+
+    let details = tts
+      .create_audio_detailed(String::from("obrigado pela doação"), options)
+      .await?;
+    assert_eq!(
+      vec!["https://files.soundoftext.com/sound_id.mp3"],
+      details.urls
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_synthesis_returns_the_downloaded_audio() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let audio = tts.test_synthesis().await?;
+
+    assert_eq!("https://files.soundoftext.com/sound_id.mp3", audio.url);
+    assert_eq!(MP3_FILE, &audio.bytes[..]);
+    assert_eq!(vec![String::from("teste de áudio")], texts(&requests));
+
+    Ok(())
+  }
+
+  #[test]
+  fn redacts_text_in_logs_when_enabled() {
+    let message = "meu cpf é 123.456.789-00";
+
+    let tts = Tts::new(
+      Config {
+        redact_logs: true,
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    let loggable = tts.loggable_text(message);
+    assert!(
+      !loggable.contains("123.456.789-00"),
+      "loggable={}",
+      loggable
+    );
+    assert!(loggable.contains("len=24"), "loggable={}", loggable);
+    assert_eq!(loggable, tts.loggable_text(message));
+    assert_ne!(loggable, tts.loggable_text("outra mensagem"));
+
+    let tts = Tts::new(
+      Config {
+        redact_logs: false,
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    assert_eq!(message, tts.loggable_text(message));
+  }
+
+  #[test]
+  fn validate_rejects_messages_that_would_not_be_read() {
+    // The http client has no expectations, it panics if validate makes a request.
+    let tts = Tts::new(
+      Config {
+        max_message_len: Some(20),
+        overflow_policy: OverflowPolicy::Reject,
+        blocked_words: vec![String::from("porra")],
+        blocked_word_policy: BlockedWordPolicy::Reject,
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    let tests = vec![
+      ("", Err(TtsError::EmptyMessage)),
+      ("   ", Err(TtsError::EmptyMessage)),
+      (
+        "que live boa demais, valeu pela live",
+        Err(TtsError::MessageTooLong { len: 36, max: 20 }),
+      ),
+      (
+        "que porra é essa",
+        Err(TtsError::BlockedWord(String::from("porra"))),
+      ),
+      ("привет", Err(TtsError::UnsupportedScript)),
+      ("🎉🎉🎉 !!!", Err(TtsError::NoSpeakableContent)),
+      ("boa noite chat", Ok(())),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, tts.validate(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn mode_sets_the_effective_concurrency() {
+    let tests = vec![
+      (Mode::LowLatency, None, 8),
+      (Mode::Gentle, None, 1),
+      (Mode::Gentle, Some(3), 3),
+      (Mode::LowLatency, Some(0), 1),
+    ];
+
+    for (mode, max_concurrency, expected) in tests {
+      let config = Config {
+        mode,
+        max_concurrency,
+        ..Config::default()
+      };
+
+      assert_eq!(
+        expected,
+        config.tuning().max_concurrency,
+        "mode={:?} max_concurrency={:?}",
+        mode,
+        max_concurrency
+      );
+    }
+
+    let config = Config {
+      mode: Mode::Gentle,
+      retries: Some(0),
+      ..Config::default()
+    };
+    assert_eq!(0, config.tuning().retries);
+    assert_eq!(Mode::Gentle.tuning().backoff, config.tuning().backoff);
+  }
+
+  #[tokio::test]
+  async fn donation_audio_reads_the_amount_and_wraps_the_message_once(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        message_prefix: Some(String::from("Mensagem:")),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts
+      .create_donation_audio("Fulano", "10,05", "valeu pela live, R$ 1,00 pro café")
+      .await?;
+
+    assert_eq!(
+      vec![String::from(
+        "Mensagem: Fulano doou dez reais e cinco centavos. valeu pela live, um real pro café"
+      )],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn first_donations_start_with_the_intro() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 30,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts.create_donation_audio("Fulano", "10", "valeu").await?;
+    assert_eq!(vec!["Fulano doou dez reais. valeu"], texts(&requests));
+
+    requests.lock().unwrap().clear();
+
+    tts
+      .create_first_donation_audio("Fulano", "10", "valeu")
+      .await?;
+    assert_eq!(
+      vec!["primeira doação de Fulano!", "Fulano doou dez reais. valeu"],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn planned_voices_match_the_voices_used_for_synthesis(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        markup_policy: MarkupPolicy::Apply,
+        tier_voices: HashMap::from([(
+          Tier::HighQuality,
+          Voice {
+            engine: String::from("google"),
+            voice: String::from("pt-PT"),
+          },
+        )]),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let text = "olá [voice=en-US]hello everyone[/voice] tchau [voice=en-US]bye[/voice]";
+
+    let planned = tts.planned_voices(text, &CallOptions::default());
+
+    tts.create_audio(text.to_string()).await?;
+
+    let mut used: Vec<String> = Vec::new();
+    for request in requests.lock().unwrap().iter() {
+      let voice = request["data"]["voice"].as_str().unwrap().to_string();
+      if !used.contains(&voice) {
+        used.push(voice);
+      }
+    }
+
+    assert_eq!(vec![String::from("pt-BR"), String::from("en-US")], planned);
+    assert_eq!(planned, used);
+    assert_eq!(
+      vec![String::from("pt-BR")],
+      tts.planned_voices("olá pessoal", &CallOptions::default())
+    );
+    assert!(tts.planned_voices("", &CallOptions::default()).is_empty());
+
+    let high = CallOptions {
+      tier: Tier::HighQuality,
+      ..CallOptions::default()
+    };
+    assert_eq!(
+      vec![String::from("pt-PT")],
+      tts.planned_voices("olá pessoal", &high)
+    );
+    requests.lock().unwrap().clear();
+    tts
+      .create_audio_detailed(String::from("olá pessoal"), high)
+      .await?;
+    assert_eq!(
+      "pt-PT",
+      requests.lock().unwrap()[0]["data"]["voice"]
+        .as_str()
+        .unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reads_the_words_of_messages_with_emotes_and_emoji_spam(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts
+      .create_audio(String::from("PogChamp obrigado!!! 🎉🎉🎉"))
+      .await?;
+
+    assert_eq!(vec![String::from("obrigado!")], texts(&requests));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reports_the_fallback_voice_that_created_each_chunk(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let fallback = Voice {
+      engine: String::from("google"),
+      voice: String::from("pt-PT"),
+    };
+
+    let tts = Tts::new(
+      Config {
+        fallback_voices: vec![fallback.clone()],
+        retries: Some(1),
+        backoff: Some(Backoff {
+          initial: Duration::ZERO,
+          max: Duration::ZERO,
+          jitter: Jitter::None,
+        }),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_failing_for(
+        Arc::clone(&requests),
+        &["pt-BR"],
+      )),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(String::from("olá"), CallOptions::default())
+      .await?;
+
+    assert_eq!(
+      vec![ChunkDetails {
+        url: String::from("https://files.soundoftext.com/sound_id.mp3"),
+        voice: fallback,
+        // Two attempts with the voice of the message and one with the fallback voice.
+        attempts: 3,
+        pause_after: Duration::ZERO,
+        rate: None,
+      }],
+      details.chunks
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn reports_pauses_for_repeated_punctuation() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        punctuation_policy: PunctuationPolicy::Pause {
+          per_mark: Duration::from_millis(200),
+          max: Duration::from_secs(1),
+        },
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("e o ganhador é...... você!"),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(
+      vec![String::from("e o ganhador é."), String::from("você!")],
+      texts(&requests)
+    );
+    assert_eq!(
+      vec![Duration::from_secs(1), Duration::ZERO],
+      details
+        .chunks
+        .iter()
+        .map(|chunk| chunk.pause_after)
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(Err(TtsError::NoSpeakableContent), tts.validate("......"));
+    assert_eq!(
+      Err(TtsError::NoSpeakableContent),
+      tts.validate("@streamer @mod")
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn returns_the_fallback_audio_when_every_voice_fails(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let fallback_voice = Voice {
+      engine: String::from("google"),
+      voice: String::from("pt-PT"),
+    };
+
+    let config = Config {
+      fallback_voices: vec![fallback_voice],
+      retries: Some(0),
+      ..Config::default()
+    };
+
+    let tts = Tts::new(
+      Config {
+        fallback_audio: Some(String::from("./assets/nao_consegui_ler.mp3")),
+        ..config.clone()
+      },
+      Arc::new(mock_http_client_failing_for(
+        Arc::clone(&requests),
+        &["pt-BR", "pt-PT"],
+      )),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(String::from("olá"), CallOptions::default())
+      .await?;
+
+    assert_eq!(
+      vec![String::from("./assets/nao_consegui_ler.mp3")],
+      details.urls
+    );
+    assert_eq!(vec![SynthesisWarning::FallbackAudio], details.warnings);
+
+    // Without fallback audio the error is returned.
+    let tts = Tts::new(
+      config,
+      Arc::new(mock_http_client_failing_for(
+        Arc::clone(&requests),
+        &["pt-BR", "pt-PT"],
+      )),
+      None,
+    );
+
+    assert!(tts.create_audio(String::from("olá")).await.is_err());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn merged_audio_has_a_break_only_where_the_voice_changes(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 15,
+        voice_break: Duration::from_millis(100),
+        markup_policy: MarkupPolicy::Apply,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let merged = tts
+      .create_audio_merged(
+        String::from("olá pessoal. tudo bem? [voice=en-US]hello[/voice]"),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(
+      vec![
+        String::from("olá pessoal."),
+        String::from("tudo bem?"),
+        String::from("hello")
+      ],
+      texts(&requests)
+    );
+
+    let frame = mp3::strip_id3(MP3_FILE);
+    let header = mp3::first_frame_header(MP3_FILE).unwrap();
+
+    assert_eq!(
+      [
+        frame,
+        frame,
+        &mp3::silence(&header, Duration::from_millis(100)),
+        frame
+      ]
+      .concat(),
+      merged.to_vec()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn bleeps_are_read_from_disk_when_merged() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 12,
+        blocked_words: vec![String::from("porra")],
+        blocked_word_policy: BlockedWordPolicy::BleepChunk,
+        bleep_audio: Some(String::from("./assets/henri.mp3")),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let merged = tts
+      .create_audio_merged(
+        String::from("bom dia. que porra. boa noite."),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(vec!["bom dia.", "boa noite."], texts(&requests));
+
+    let frame = mp3::strip_id3(MP3_FILE);
+    let bleep = std::fs::read("./assets/henri.mp3")?;
+
+    assert_eq!(
+      [frame, mp3::strip_id3(&bleep), frame].concat(),
+      merged.to_vec()
+    );
+
+    Ok(())
+  }
+
+  #[cfg(feature = "audio-analysis")]
+  #[tokio::test]
+  async fn bleeps_are_read_from_disk_as_pcm() -> Result<(), Box<dyn std::error::Error>> {
+    let tts = Tts::new(
+      Config {
+        blocked_words: vec![String::from("porra")],
+        blocked_word_policy: BlockedWordPolicy::BleepChunk,
+        bleep_audio: Some(String::from("./assets/henri.mp3")),
+        ..Config::default()
+      },
+      // Every chunk is bleeped, nothing is requested.
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    let buffer = PcmBuffer::new(1_000_000);
+
+    let format = tts
+      .create_audio_pcm(String::from("que porra"), CallOptions::default(), &buffer)
+      .await?;
+
+    assert_eq!(Some(format), buffer.format());
+    assert!(!buffer.is_empty());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn sends_progress_until_the_synthesis_finishes() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 15,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let (synthesis, mut receiver) =
+      tts.create_audio_with_progress(String::from("olá pessoal. tudo bem?"));
+
+    let collect_progress = async move {
+      let mut events = Vec::new();
+      // Returns None once the channel is closed.
+      while let Some(event) = receiver.recv().await {
+        events.push(event);
+      }
+      events
+    };
+
+    let (urls, events) = tokio::join!(synthesis, collect_progress);
+
+    let url = String::from("https://files.soundoftext.com/sound_id.mp3");
+
+    assert_eq!(vec![url.clone(), url.clone()], urls?);
+    assert_eq!(
+      vec![
+        SynthesisProgress::Started { chunks: 2 },
+        SynthesisProgress::ChunkCreated {
+          index: 0,
+          url: url.clone()
+        },
+        SynthesisProgress::ChunkCreated { index: 1, url },
+        SynthesisProgress::Finished,
+      ],
+      events
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_speed_ramp_rates() {
+    let speed_ramp = SpeedRamp {
+      min_chunks: 3,
+      max_rate: 120,
+    };
+
+    let tests = vec![
+      (0, vec![]),
+      (3, vec![None, None, None]),
+      (5, vec![None, Some(105), Some(110), Some(115), Some(120)]),
+    ];
+
+    for (chunks, expected) in tests {
+      assert_eq!(expected, speed_ramp.rates(chunks), "chunks={}", chunks);
+    }
+  }
+
+  #[tokio::test]
+  async fn speeding_up_long_messages_fails_with_soundoftext(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 10,
+        speed_ramp: Some(SpeedRamp {
+          min_chunks: 2,
+          max_rate: 130,
+        }),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    assert!(!tts.capabilities().rate_control);
+
+    let err = tts
+      .create_audio(String::from("um dois. tres quatro. cinco seis. sete oito."))
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&TtsError::RateControlUnsupported),
+      err.downcast_ref::<TtsError>()
+    );
+    assert!(requests.lock().unwrap().is_empty());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn limits_how_many_chunks_poll_at_the_same_time() -> Result<(), Box<dyn std::error::Error>>
+  {
+    #[derive(Default)]
+    struct Polls {
+      /// How many times each sound was polled.
+      counts: HashMap<String, u32>,
+      /// Sounds that were polled but are not ready yet.
+      active: usize,
+      max_active: usize,
+    }
+
+    let polls = Arc::new(Mutex::new(Polls::default()));
+    let next_id = Arc::new(Mutex::new(0));
+
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(move |_, _, _| {
+      let mut next_id = next_id.lock().unwrap();
+      *next_id += 1;
+
+      Ok(PostResponse {
+        status: 200,
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "id": format!("sound_{}", next_id)
+        }))?),
+      })
+    });
+
+    http_client.expect_get().returning({
+      let polls = Arc::clone(&polls);
+      move |url, _| {
+        let id = url.rsplit('/').next().unwrap().to_string();
+
+        let mut polls = polls.lock().unwrap();
+
+        let count = {
+          let count = polls.counts.entry(id.clone()).or_default();
+          *count += 1;
+          *count
+        };
+
+        if count == 1 {
+          polls.active += 1;
+          polls.max_active = polls.max_active.max(polls.active);
+        }
+
+        // Each audio file is ready on the third poll.
+        let body = if count < 3 {
+          serde_json::json!({ "status": "Pending" })
+        } else {
+          polls.active -= 1;
+          serde_json::json!({
+            "status": "Done",
+            "location": format!("https://files.soundoftext.com/{}.mp3", id)
+          })
+        };
+
+        Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from(serde_json::to_vec(&body)?),
+        })
+      }
+    });
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 7,
+        max_concurrency: Some(8),
+        poll_interval: Some(Duration::from_millis(5)),
+        max_concurrent_polls: 2,
+        ..Config::default()
+      },
+      Arc::new(http_client),
+      None,
+    );
+
+    let urls = tts
+      .create_audio(String::from(
+        "um. dois. tres. cinco. seis. sete. oito. nove.",
+      ))
+      .await?;
+
+    assert_eq!(8, urls.len());
+
+    let polls = polls.lock().unwrap();
+    assert_eq!(8, polls.counts.len());
+    assert_eq!(2, polls.max_active);
+
+    Ok(())
+  }
+
+  /// Returns a client that answers the first create request and the first poll
+  /// with an empty body, and then works.
+  fn mock_http_client_with_empty_responses(
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+  ) -> MockHttpClient {
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(move |_, body, _| {
+      let mut requests = requests.lock().unwrap();
+      requests.push(serde_json::from_slice(&body)?);
+
+      if requests.len() == 1 {
+        return Ok(PostResponse {
+          status: 200,
+          body: Bytes::new(),
+        });
+      }
+
+      Ok(PostResponse {
+        status: 200,
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "id": "sound_id"
+        }))?),
+      })
+    });
+
+    let polls = Arc::new(Mutex::new(0));
+    http_client.expect_get().returning(move |_, _| {
+      let mut polls = polls.lock().unwrap();
+      *polls += 1;
+
+      if *polls == 1 {
+        return Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from_static(b" \n"),
+        });
+      }
+
+      Ok(GetResponse {
+        status: 200,
+        headers: HeaderMap::new(),
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "status": "Done",
+          "location": "https://files.soundoftext.com/sound_id.mp3"
+        }))?),
+      })
+    });
+
+    http_client
+  }
+
+  #[tokio::test]
+  async fn retries_empty_responses() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        retries: Some(2),
+        backoff: Some(Backoff {
+          initial: Duration::ZERO,
+          max: Duration::ZERO,
+          jitter: Jitter::None,
+        }),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_with_empty_responses(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(String::from("olá"), CallOptions::default())
+      .await?;
+
+    // The create request was empty the first time and the poll the second time.
+    assert_eq!(3, details.chunks[0].attempts);
+    assert_eq!(
+      "https://files.soundoftext.com/sound_id.mp3",
+      details.chunks[0].url
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn empty_responses_are_reported() -> Result<(), Box<dyn std::error::Error>> {
+    let tts = Tts::new(
+      Config {
+        retries: Some(0),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_with_empty_responses(Arc::new(Mutex::new(
+        Vec::new(),
+      )))),
+      None,
+    );
+
+    // Empty create response.
+    let err = tts.create_audio(String::from("olá")).await.unwrap_err();
+    assert_eq!(
+      Some(&TtsError::EmptyResponse),
+      err.downcast_ref::<TtsError>()
+    );
+
+    // Empty poll response.
+    let err = tts.create_audio(String::from("olá")).await.unwrap_err();
+    assert_eq!(
+      Some(&TtsError::EmptyResponse),
+      err.downcast_ref::<TtsError>()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn bypassing_the_cache_creates_the_audio_again() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let writes = Arc::new(Mutex::new(Vec::new()));
+
+    let mut cache = MockCache::new();
+    cache.expect_get().never();
+    {
+      let writes = Arc::clone(&writes);
+      cache.expect_put().returning(move |key, value, _| {
+        writes.lock().unwrap().push((key, value));
+        Ok(())
+      });
+    }
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      Some(Arc::new(cache)),
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("olá"),
+        CallOptions {
+          bypass_cache: true,
+          ..CallOptions::default()
+        },
+      )
+      .await?;
+
+    tts.shutdown().await;
+
+    assert_eq!(
+      vec!["https://files.soundoftext.com/sound_id.mp3"],
+      details.urls
+    );
+    assert_eq!(vec!["olá"], texts(&requests));
+    assert!(writes.lock().unwrap().is_empty());
+
+    tts
+      .create_audio_detailed(
+        String::from("olá"),
+        CallOptions {
+          bypass_cache: true,
+          refresh_cache: true,
+          ..CallOptions::default()
+        },
+      )
+      .await?;
+
+    tts.shutdown().await;
+
+    assert_eq!(2, requests.lock().unwrap().len());
+    assert_eq!(
+      vec![(
+        audio_url_cache_key("olá", &Voice::default(), None),
+        b"https://files.soundoftext.com/sound_id.mp3".to_vec()
+      )],
+      *writes.lock().unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn captures_the_latest_exchanges_of_a_failed_synthesis(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let tts = Tts::new(
+      Config {
+        retries: Some(2),
+        backoff: Some(Backoff {
+          initial: Duration::ZERO,
+          max: Duration::ZERO,
+          jitter: Jitter::None,
+        }),
+        redact_logs: true,
+        captured_exchanges: Some(2),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_failing_for(
+        Arc::new(Mutex::new(Vec::new())),
+        &["pt-BR"],
+      )),
+      None,
+    );
+
+    assert!(tts
+      .create_audio(String::from("minha senha é segredo"))
+      .await
+      .is_err());
+
+    // Three attempts were made, only the last two are kept.
+    let exchanges = tts.last_exchanges();
+    assert_eq!(2, exchanges.len());
+
+    for exchange in exchanges {
+      assert_eq!("POST", exchange.method);
+      assert_eq!("https://api.soundoftext.com/sounds", exchange.url);
+
+      let request = exchange.request.unwrap();
+      assert!(request.contains("voice=pt-BR"), "request={}", request);
+      assert!(!request.contains("segredo"), "request={}", request);
+
+      assert_eq!(None, exchange.status);
+      let error = exchange.response.unwrap_err();
+      assert!(error.contains("service unavailable"), "error={}", error);
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn captures_the_status_of_responses() -> Result<(), Box<dyn std::error::Error>> {
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(|_, _, _| {
+      Ok(PostResponse {
+        status: 403,
+        body: Bytes::from_static(b"{\"detail\":\"forbidden\"}"),
+      })
+    });
+
+    let tts = Tts::new(
+      Config {
+        retries: Some(0),
+        captured_exchanges: Some(1),
+        ..Config::default()
+      },
+      Arc::new(http_client),
+      None,
+    );
+
+    assert!(tts.create_audio(String::from("olá")).await.is_err());
+
+    let exchanges = tts.last_exchanges();
+    assert_eq!(1, exchanges.len());
+    assert_eq!(Some(403), exchanges[0].status);
+    assert_eq!(
+      Ok(String::from("{\"detail\":\"forbidden\"}")),
+      exchanges[0].response
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn exchanges_are_not_captured_by_default() -> Result<(), Box<dyn std::error::Error>> {
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::new(Mutex::new(Vec::new())))),
+      None,
+    );
+
+    tts.create_audio(String::from("olá")).await?;
+
+    assert!(tts.last_exchanges().is_empty());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn retries_downloads_with_their_own_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let downloads = Arc::new(Mutex::new(0));
+
+    let mut http_client = MockHttpClient::new();
+
+    {
+      let requests = Arc::clone(&requests);
+      http_client.expect_post().returning(move |_, body, _| {
+        requests
+          .lock()
+          .unwrap()
+          .push(serde_json::from_slice::<serde_json::Value>(&body)?);
+
+        Ok(PostResponse {
+          status: 200,
+          body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+            "id": "sound_id"
+          }))?),
+        })
+      });
+    }
+
+    {
+      let downloads = Arc::clone(&downloads);
+      http_client.expect_get().returning(move |url, _| {
+        if !url.ends_with(".mp3") {
+          return Ok(GetResponse {
+            status: 200,
+            headers: HeaderMap::new(),
+            body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+              "status": "Done",
+              "location": "https://files.soundoftext.com/sound_id.mp3"
+            }))?),
+          });
+        }
+
+        let mut downloads = downloads.lock().unwrap();
+        *downloads += 1;
+
+        // The file server is unavailable twice.
+        if *downloads <= 2 {
+          return Ok(GetResponse {
+            status: 503,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(b"Service Unavailable"),
+          });
+        }
+
+        Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from_static(MP3_FILE),
+        })
+      });
+    }
+
+    let tts = Tts::new(
+      Config {
+        retries: Some(0),
+        download_retry: DownloadRetry {
+          retries: 2,
+          backoff: Backoff {
+            initial: Duration::ZERO,
+            max: Duration::ZERO,
+            jitter: Jitter::None,
+          },
+          statuses: vec![503],
+        },
+        ..Config::default()
+      },
+      Arc::new(http_client),
+      None,
+    );
+
+    let audio = tts.test_synthesis().await?;
+
+    assert_eq!(MP3_FILE, &audio.bytes[..]);
+    assert_eq!(3, *downloads.lock().unwrap());
+    // Creating the audio was not retried.
+    assert_eq!(1, requests.lock().unwrap().len());
+
+    // Out of retries.
+    *downloads.lock().unwrap() = 0;
+    let tts = Tts::new(
+      Config {
+        download_retry: DownloadRetry {
+          retries: 1,
+          ..tts.config.download_retry.clone()
+        },
+        ..Config::default()
+      },
+      Arc::clone(&tts.http_client),
+      None,
+    );
+
+    assert!(tts.test_synthesis().await.is_err());
+    assert_eq!(2, *downloads.lock().unwrap());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn cancels_a_synthesis_by_correlation_id() -> Result<(), Box<dyn std::error::Error>> {
+    let polls = Arc::new(Mutex::new(0));
+
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(|_, _, _| {
+      Ok(PostResponse {
+        status: 200,
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "id": "sound_id"
+        }))?),
+      })
+    });
+
+    {
+      let polls = Arc::clone(&polls);
+      // The audio file is never ready.
+      http_client.expect_get().returning(move |_, _| {
+        *polls.lock().unwrap() += 1;
+
+        Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+            "status": "Pending"
+          }))?),
+        })
+      });
+    }
+
+    let tts = Arc::new(Tts::new(
+      Config {
+        poll_interval: Some(Duration::from_millis(5)),
+        ..Config::default()
+      },
+      Arc::new(http_client),
+      None,
+    ));
+
+    let synthesis = tokio::spawn({
+      let tts = Arc::clone(&tts);
+      async move {
+        tts
+          .create_audio_detailed(
+            String::from("obrigado pela doação"),
+            CallOptions {
+              correlation_id: Some(String::from("donation-1")),
+              ..CallOptions::default()
+            },
+          )
+          .await
+      }
+    });
+
+    while *polls.lock().unwrap() == 0 {
+      tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    assert_eq!(vec![String::from("donation-1")], tts.in_flight());
+    assert!(!tts.cancel("donation-2"));
+    assert!(tts.cancel("donation-1"));
+
+    let err = synthesis.await?.unwrap_err();
+    assert_eq!(Some(&TtsError::Cancelled), err.downcast_ref::<TtsError>());
+
+    // Nothing is polling anymore.
+    let polls_after_cancel = *polls.lock().unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(polls_after_cancel, *polls.lock().unwrap());
+
+    assert!(tts.in_flight().is_empty());
+    assert!(!tts.cancel("donation-1"));
+
+    Ok(())
+  }
+
+  #[test]
+  fn reports_the_capabilities_of_soundoftext() {
+    let tts = Tts::new(Config::default(), Arc::new(MockHttpClient::new()), None);
+
+    let capabilities = tts.capabilities();
+    assert_eq!(vec!["mp3"], capabilities.formats);
+    assert!(!capabilities.speech_marks);
+    assert!(!capabilities.ssml);
+    assert_eq!(None, capabilities.max_input_len);
+
+    let tts = Tts::new(
+      Config {
+        max_message_len: Some(300),
+        overflow_policy: OverflowPolicy::Reject,
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    assert_eq!(Some(300), tts.capabilities().max_input_len);
+  }
+
+  #[tokio::test]
+  async fn choosing_a_sample_rate_fails_with_soundoftext() -> Result<(), Box<dyn std::error::Error>>
+  {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        sample_rate: Some(16_000),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let err = tts.create_audio(String::from("olá")).await.unwrap_err();
+    assert_eq!(
+      Some(&TtsError::UnsupportedSampleRate(16_000)),
+      err.downcast_ref::<TtsError>()
+    );
+    assert!(requests.lock().unwrap().is_empty());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn sends_the_sample_rate_when_the_api_supports_it() -> Result<(), Box<dyn std::error::Error>>
+  {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        sample_rate: Some(16_000),
+        api_sample_rates: vec![16_000, 24_000],
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    assert_eq!(vec![16_000, 24_000], tts.capabilities().sample_rates);
+
+    tts.create_audio(String::from("olá")).await?;
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(1, requests.len());
+    assert_eq!(16_000, requests[0]["data"]["sample_rate"]);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn does_not_send_the_sample_rate_by_default() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts.create_audio(String::from("olá")).await?;
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(1, requests.len());
+    assert!(requests[0]["data"].get("sample_rate").is_none());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn keeps_stats_of_the_messages_synthesized() -> Result<(), Box<dyn std::error::Error>> {
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 20,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::new(Mutex::new(Vec::new())))),
+      None,
+    );
+
+    for text in [
+      "olá",
+      "boa noite",
+      "muito obrigado pela doação. a live está ótima hoje, pessoal.",
+    ] {
+      tts.create_audio(String::from(text)).await?;
+    }
+
+    let stats = tts.stats();
+
+    assert_eq!(3, stats.original_len.count);
+    assert_eq!(3 + 9 + 60, stats.original_len.sum);
+    // Two short messages and one between 50 and 100 characters.
+    assert_eq!(2, stats.original_len.buckets[0].count);
+    assert_eq!(Some(100), stats.original_len.buckets[3].le);
+    assert_eq!(1, stats.original_len.buckets[3].count);
+
+    assert_eq!(3, stats.normalized_len.count);
+
+    // The short messages have a single chunk each.
+    assert_eq!(Some(1), stats.chunks.buckets[0].le);
+    assert_eq!(2, stats.chunks.buckets[0].count);
+    assert_eq!(3, stats.chunks.count);
+    assert!(stats.chunks.sum >= 5, "chunks={:?}", stats.chunks);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn limits_how_many_chunks_each_voice_creates_at_the_same_time(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Default)]
+    struct Requests {
+      /// The voice of each sound being created.
+      voices: HashMap<String, String>,
+      active: HashMap<String, usize>,
+      max_active: HashMap<String, usize>,
+    }
+
+    let requests = Arc::new(Mutex::new(Requests::default()));
+
+    let mut http_client = MockHttpClient::new();
+
+    {
+      let requests = Arc::clone(&requests);
+      let mut next_id = 0;
+      http_client.expect_post().returning(move |_, body, _| {
+        let request: serde_json::Value = serde_json::from_slice(&body)?;
+        let voice = request["data"]["voice"].as_str().unwrap().to_string();
+
+        next_id += 1;
+        let id = format!("sound_{}", next_id);
+
+        let mut requests = requests.lock().unwrap();
+        requests.voices.insert(id.clone(), voice.clone());
+        let active = {
+          let active = requests.active.entry(voice.clone()).or_default();
+          *active += 1;
+          *active
+        };
+        let max_active = requests.max_active.entry(voice).or_default();
+        *max_active = (*max_active).max(active);
+
+        Ok(PostResponse {
+          status: 200,
+          body: Bytes::from(serde_json::to_vec(&serde_json::json!({ "id": id }))?),
+        })
+      });
+    }
+
+    {
+      let requests = Arc::clone(&requests);
+      let mut polls: HashMap<String, u32> = HashMap::new();
+      http_client.expect_get().returning(move |url, _| {
+        let id = url.rsplit('/').next().unwrap().to_string();
+
+        let count = polls.entry(id.clone()).or_default();
+        *count += 1;
+
+        // Each audio file is ready on the second poll.
+        let body = if *count < 2 {
+          serde_json::json!({ "status": "Pending" })
+        } else {
+          let mut requests = requests.lock().unwrap();
+          let voice = requests.voices[&id].clone();
+          *requests.active.get_mut(&voice).unwrap() -= 1;
+
+          serde_json::json!({
+            "status": "Done",
+            "location": format!("https://files.soundoftext.com/{}.mp3", id)
+          })
+        };
+
+        Ok(GetResponse {
+          status: 200,
+          headers: HeaderMap::new(),
+          body: Bytes::from(serde_json::to_vec(&body)?),
+        })
+      });
+    }
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 7,
+        max_concurrency: Some(8),
+        max_concurrent_polls: 8,
+        poll_interval: Some(Duration::from_millis(5)),
+        voice_concurrency: HashMap::from([(String::from("pt-BR"), 1), (String::from("en-US"), 2)]),
+        markup_policy: MarkupPolicy::Apply,
+        ..Config::default()
+      },
+      Arc::new(http_client),
+      None,
+    );
+
+    let urls = tts
+      .create_audio(String::from(
+        "um. dois. tres. cinco. [voice=en-US]one. two. three. four.[/voice]",
+      ))
+      .await?;
+
+    assert_eq!(8, urls.len());
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(8, requests.voices.len());
+    assert_eq!(1, requests.max_active["pt-BR"]);
+    assert_eq!(2, requests.max_active["en-US"]);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn synthesizes_chunks_in_the_configured_order() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 12,
+        max_concurrency: Some(1),
+        chunk_order: ChunkOrder::new(|chunks| (0..chunks.len()).rev().collect()),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("era uma vez. um pix. e a piada."),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(
+      vec!["e a piada.", "um pix.", "era uma vez."],
+      texts(&requests)
+    );
+    assert_eq!(3, details.urls.len());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn only_chunks_with_blocked_words_are_filtered() -> Result<(), Box<dyn std::error::Error>> {
+    for (policy, expected) in [
+      (
+        BlockedWordPolicy::SkipChunk,
+        vec![
+          ChunkFilter::Allowed,
+          ChunkFilter::Skipped,
+          ChunkFilter::Allowed,
+        ],
+      ),
+      (
+        BlockedWordPolicy::BleepChunk,
+        vec![
+          ChunkFilter::Allowed,
+          ChunkFilter::Bleeped,
+          ChunkFilter::Allowed,
+        ],
+      ),
+    ] {
+      let requests = Arc::new(Mutex::new(Vec::new()));
+
+      let tts = Tts::new(
+        Config {
+          max_chunk_len: 12,
+          blocked_words: vec![String::from("porra")],
+          blocked_word_policy: policy,
+          bleep_audio: Some(String::from("./assets/bleep.mp3")),
+          ..Config::default()
+        },
+        Arc::new(mock_http_client(Arc::clone(&requests))),
+        None,
+      );
+
+      let details = tts
+        .create_audio_detailed(
+          String::from("bom dia. que porra. boa noite."),
+          CallOptions::default(),
+        )
+        .await?;
+
+      assert_eq!(vec!["bom dia.", "boa noite."], texts(&requests));
+      assert_eq!(expected, details.filtered);
+
+      let bleeped = policy == BlockedWordPolicy::BleepChunk;
+      assert_eq!(if bleeped { 3 } else { 2 }, details.urls.len());
+      assert_eq!(
+        bleeped,
+        details.urls.contains(&String::from("./assets/bleep.mp3"))
+      );
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn only_the_first_sentences_are_synthesized() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_sentences: Some(2),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("Um. Dois. Três. Quatro. Cinco. Seis."),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(vec!["Um. Dois."], texts(&requests));
+    assert_eq!(
+      vec![SynthesisWarning::SentencesDropped { count: 4 }],
+      details.warnings
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn segments_are_read_by_their_voices_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 20,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let urls = tts
+      .create_audio_segments(vec![
+        (
+          String::from("olá pessoal. tudo bem?"),
+          String::from("pt-BR"),
+        ),
+        (String::from("hello everyone"), String::from("en-US")),
+      ])
+      .await?;
+
+    assert_eq!(3, urls.len());
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(
+      vec![
+        ("olá pessoal.", "pt-BR"),
+        ("tudo bem?", "pt-BR"),
+        ("hello everyone", "en-US"),
+      ],
+      requests
+        .iter()
+        .map(|request| (
+          request["data"]["text"].as_str().unwrap(),
+          request["data"]["voice"].as_str().unwrap()
+        ))
+        .collect::<Vec<_>>()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn segments_are_checked_like_messages() -> Result<(), Box<dyn std::error::Error>> {
+    let segments = || {
+      vec![
+        (String::from("bom dia."), String::from("pt-BR")),
+        (String::from("que porra."), String::from("pt-BR")),
+        (String::from("good night."), String::from("en-US")),
+      ]
+    };
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        blocked_words: vec![String::from("porra")],
+        blocked_word_policy: BlockedWordPolicy::Reject,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let err = tts.create_audio_segments(segments()).await.unwrap_err();
+    assert_eq!(
+      Some(&TtsError::BlockedWord(String::from("porra"))),
+      err.downcast_ref::<TtsError>()
+    );
+    assert!(requests.lock().unwrap().is_empty());
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        blocked_words: vec![String::from("porra")],
+        blocked_word_policy: BlockedWordPolicy::SkipChunk,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let urls = tts.create_audio_segments(segments()).await?;
+
+    assert_eq!(2, urls.len());
+    assert_eq!(vec!["bom dia.", "good night."], texts(&requests));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn markup_written_by_viewers_is_removed_by_default(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config::default(),
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("olá [voice=en-US]hello[/voice] [pause=18446744073709551615] tchau"),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(vec!["olá hello tchau"], texts(&requests));
+    assert_eq!(Voice::default(), details.chunks[0].voice);
+    assert_eq!(Duration::ZERO, details.chunks[0].pause_after);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn identical_chunks_are_synthesized_once() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 10,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let details = tts
+      .create_audio_detailed(
+        String::from("obrigado. valeu. obrigado."),
+        CallOptions::default(),
+      )
+      .await?;
+
+    assert_eq!(vec!["obrigado.", "valeu."], texts(&requests));
+    assert_eq!(3, details.urls.len());
+    assert_eq!(details.chunks[0], details.chunks[2]);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn senders_over_the_rate_limit_are_throttled() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        sender_rate_limit: Some(SenderRateLimit {
+          max_messages: 2,
+          window: Duration::from_secs(60),
+          exempt_senders: vec![String::from("streamer")],
+        }),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts
+      .create_audio_for_sender("spammer", SenderRole::Regular, String::from("um"))
+      .await?;
+    tts
+      .create_audio_for_sender("spammer", SenderRole::Regular, String::from("dois"))
+      .await?;
+
+    let err = tts
+      .create_audio_for_sender("spammer", SenderRole::Regular, String::from("três"))
+      .await
+      .unwrap_err();
+    assert_eq!(
+      Some(&TtsError::SenderRateLimited(String::from("spammer"))),
+      err.downcast_ref::<TtsError>()
+    );
+
+    // Other senders are not affected.
+    tts
+      .create_audio_for_sender("viewer", SenderRole::Regular, String::from("olá"))
+      .await?;
+    for _ in 0..3 {
+      tts
+        .create_audio_for_sender("streamer", SenderRole::Regular, String::from("oi"))
+        .await?;
+    }
+
+    assert_eq!(
+      vec!["um", "dois", "olá", "oi", "oi", "oi"],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn subscribers_are_read_first_and_throttled_less() -> Result<(), Box<dyn std::error::Error>>
+  {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Arc::new(Tts::new(
+      Config {
+        sender_rate_limit: Some(SenderRateLimit {
+          max_messages: 1,
+          window: Duration::from_secs(60),
+          exempt_senders: Vec::new(),
+        }),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    ));
+
+    let queue = QueuedTts::new(
+      Arc::clone(&tts) as Arc<dyn contracts::tts::TextToSpeech>,
+      QueueConfig {
+        max_concurrency: 1,
+        ..QueueConfig::default()
+      },
+    );
+
+    let mut submissions = Vec::new();
+    let mut throttled = Vec::new();
+    for (sender_id, role, text) in [
+      ("viewer", SenderRole::Regular, "primeira"),
+      ("viewer", SenderRole::Regular, "spam"),
+      ("other", SenderRole::Regular, "chat"),
+      ("fan", SenderRole::Subscriber, "sou inscrito"),
+      ("fan", SenderRole::Subscriber, "de novo"),
+      ("fan", SenderRole::Subscriber, "mais uma"),
+    ] {
+      match tts.admit_sender(sender_id, role) {
+        Ok(priority) => submissions.push(queue.submit(text.to_string(), priority)),
+        Err(err) => throttled.push((text, err)),
+      }
+    }
+
+    // The first message takes the only slot, the others wait in the queue.
+    for result in futures::future::join_all(submissions).await {
+      result?;
+    }
+
+    assert_eq!(
+      vec![
+        ("spam", TtsError::SenderRateLimited(String::from("viewer"))),
+        ("mais uma", TtsError::SenderRateLimited(String::from("fan"))),
+      ],
+      throttled
+    );
+    assert_eq!(
+      vec!["primeira", "sou inscrito", "de novo", "chat"],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn senders_are_always_read_by_the_same_voice() -> Result<(), Box<dyn std::error::Error>> {
+    let voice = |name: &str| Voice {
+      engine: String::from("google"),
+      voice: name.to_string(),
+    };
+
+    let sender_voices = SenderVoices {
+      pool: vec![voice("pt-BR"), voice("pt-PT"), voice("es-ES")],
+      overrides: HashMap::from([(String::from("streamer"), voice("en-US"))]),
+    };
+
+    let fulano = sender_voices.voice_for("Fulano").unwrap();
+    for _ in 0..10 {
+      assert_eq!(Some(&fulano), sender_voices.voice_for("Fulano").as_ref());
+    }
+    assert!(sender_voices.pool.contains(&fulano));
+
+    // Different senders are spread across the pool.
+    let voices: Vec<Voice> = (0..30)
+      .filter_map(|i| sender_voices.voice_for(&format!("viewer{}", i)))
+      .collect();
+    for voice in sender_voices.pool.iter() {
+      assert!(voices.contains(voice), "voice={:?}", voice);
+    }
+
+    assert_eq!(Some(voice("en-US")), sender_voices.voice_for("streamer"));
+    assert_eq!(None, SenderVoices::default().voice_for("Fulano"));
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        sender_voices: Some(sender_voices),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    for text in ["primeira doação", "segunda doação"] {
+      tts
+        .create_audio_for_sender("Fulano", SenderRole::Regular, text.to_string())
+        .await?;
+    }
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(2, requests.len());
+    for request in requests.iter() {
+      assert_eq!(fulano.voice, request["data"]["voice"]);
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn chunk_files_are_downloaded_one_at_a_time() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 15,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let dir = std::env::temp_dir().join(format!("chunk-files-{:016x}", rand::random::<u64>()));
+    std::fs::create_dir_all(&dir)?;
+    let files_on_disk = || std::fs::read_dir(&dir).unwrap().count();
+
+    let files = tts
+      .create_audio_files(
+        String::from("olá pessoal. tudo bem? até mais. tchau."),
+        CallOptions::default(),
+        &dir,
+      )
+      .await?;
+    futures::pin_mut!(files);
+
+    let mut indexes = Vec::new();
+    while let Some(file) = files.next().await {
+      let file = file?;
+      assert_eq!(1, files_on_disk());
+      assert_eq!(MP3_FILE, std::fs::read(file.path())?);
+      indexes.push(file.index);
+    }
+
+    assert_eq!(vec![0, 1, 2, 3], indexes);
+    assert_eq!(0, files_on_disk());
+
+    std::fs::remove_dir(&dir)?;
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn templated_donations_are_read_as_donations() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        donation_template: Some(DonationTemplate {
+          delimiter: '|',
+          fields: vec![
+            TemplateField::Literal(String::from("NewDonation")),
+            TemplateField::Donor,
+            TemplateField::Amount,
+            TemplateField::Message,
+          ],
+        }),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    tts
+      .create_audio_from_input(String::from("NewDonation|Fulano|10,00|valeu"))
+      .await?;
+    // Anything else is read as written.
+    tts
+      .create_audio_from_input(String::from("NewFollower|Fulano"))
+      .await?;
+
+    assert_eq!(
+      vec!["Fulano doou dez reais. valeu", "NewFollower|Fulano"],
+      texts(&requests)
+    );
+
+    Ok(())
+  }
+
+  /// Answers like `inner` but takes `delay` to return audio files.
+  struct SlowCdn {
+    inner: MockHttpClient,
+    delay: Duration,
+  }
+
+  #[async_trait]
+  impl contracts::http::HttpClient for SlowCdn {
+    async fn post(
+      &self,
+      url: &str,
+      body: Vec<u8>,
+      options: Option<PostOptions>,
+    ) -> Result<PostResponse> {
+      self.inner.post(url, body, options).await
+    }
+
+    async fn get(&self, url: &str, options: Option<GetOptions>) -> Result<GetResponse> {
+      if url.ends_with(".mp3") {
+        tokio::time::sleep(self.delay).await;
+      }
+      self.inner.get(url, options).await
+    }
+  }
+
+  #[tokio::test]
+  async fn downloads_stop_at_the_download_deadline() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 15,
+        download_deadline: Some(Duration::from_millis(50)),
+        ..Config::default()
+      },
+      Arc::new(SlowCdn {
+        inner: mock_http_client(Arc::clone(&requests)),
+        delay: Duration::from_secs(30),
+      }),
+      None,
+    );
+
+    let started_at = Instant::now();
+
+    let err = tts
+      .create_audio_merged(
+        String::from("olá pessoal. tudo bem?"),
+        CallOptions::default(),
+      )
+      .await
+      .unwrap_err();
+
+    assert_eq!(Some(&TtsError::Timeout), err.downcast_ref::<TtsError>());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    // The audio was created, only the download was too slow.
+    assert_eq!(vec!["olá pessoal.", "tudo bem?"], texts(&requests));
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn chunk_files_are_prefetched() -> Result<(), Box<dyn std::error::Error>> {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        max_chunk_len: 15,
+        prefetch_chunks: 2,
+        ..Config::default()
+      },
+      Arc::new(mock_http_client(Arc::clone(&requests))),
+      None,
+    );
+
+    let dir = std::env::temp_dir().join(format!("chunk-files-{:016x}", rand::random::<u64>()));
+    std::fs::create_dir_all(&dir)?;
+    let files_on_disk = || {
+      let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+      names.sort();
+      names
+    };
+
+    let mut files = Box::pin(
+      tts
+        .create_audio_files(
+          String::from("olá pessoal. tudo bem? até mais. tchau."),
+          CallOptions::default(),
+          &dir,
+        )
+        .await?,
+    );
+
+    let first = files.next().await.unwrap()?;
+    assert_eq!(0, first.index);
+
+    // While the first chunk is handled the next two are downloaded, but not the fourth.
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let names = files_on_disk();
+    assert_eq!(3, names.len());
+    assert!(names[2].ends_with("-2.mp3"));
+
+    drop(first);
+    let second = files.next().await.unwrap()?;
+    assert_eq!(1, second.index);
+
+    // Dropping the stream deletes the files that were not returned.
+    drop(second);
+    drop(files);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(files_on_disk().is_empty());
+
+    std::fs::remove_dir(&dir)?;
+
+    Ok(())
+  }
+
+  /// Answers 401 to requests that are not authenticated with `valid_token`.
+  fn mock_http_client_with_token(
+    valid_token: &'static str,
+    tokens: Arc<Mutex<Vec<String>>>,
+  ) -> MockHttpClient {
+    let mut http_client = MockHttpClient::new();
+
+    http_client.expect_post().returning(move |_, _, options| {
+      let token = options
+        .and_then(|options| options.headers)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(key, _)| key == "Authorization")
+        .map(|(_, value)| value)
+        .unwrap_or_default();
+
+      tokens.lock().unwrap().push(token.clone());
+
+      if token != format!("Bearer {}", valid_token) {
+        return Ok(PostResponse {
+          status: 401,
+          body: Bytes::from_static(b"{\"detail\":\"token expired\"}"),
+        });
+      }
+
+      Ok(PostResponse {
+        status: 200,
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "id": "sound_id"
+        }))?),
+      })
+    });
+
+    http_client.expect_get().returning(|_, _| {
+      Ok(GetResponse {
+        status: 200,
+        headers: HeaderMap::new(),
+        body: Bytes::from(serde_json::to_vec(&serde_json::json!({
+          "status": "Done",
+          "location": "https://files.soundoftext.com/sound_id.mp3"
+        }))?),
+      })
+    });
+
+    http_client
+  }
+
+  #[tokio::test]
+  async fn expired_credentials_are_refreshed_and_the_chunk_retried(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+    let refreshes = Arc::new(Mutex::new(0));
+
+    let tts = Tts::new(
+      Config {
+        retries: Some(0),
+        auth_headers: vec![(String::from("Authorization"), String::from("Bearer old"))],
+        refresh_credentials: Some(CredentialsRefresher::new({
+          let refreshes = Arc::clone(&refreshes);
+          move || {
+            *refreshes.lock().unwrap() += 1;
+            async {
+              Ok(vec![(
+                String::from("Authorization"),
+                String::from("Bearer new"),
+              )])
+            }
+          }
+        })),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_with_token("new", Arc::clone(&tokens))),
+      None,
+    );
+
+    let urls = tts.create_audio(String::from("olá")).await?;
+
+    assert_eq!(
+      vec![String::from("https://files.soundoftext.com/sound_id.mp3")],
+      urls
+    );
+    assert_eq!(1, *refreshes.lock().unwrap());
+    assert_eq!(vec!["Bearer old", "Bearer new"], *tokens.lock().unwrap());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn credentials_are_refreshed_once_for_chunks_that_expire_together(
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+    let refreshes = Arc::new(Mutex::new(0));
+
+    let tts = Tts::new(
+      Config {
+        retries: Some(0),
+        max_chunk_len: 10,
+        max_concurrency: Some(2),
+        auth_headers: vec![(String::from("Authorization"), String::from("Bearer old"))],
+        refresh_credentials: Some(CredentialsRefresher::new({
+          let refreshes = Arc::clone(&refreshes);
+          move || {
+            *refreshes.lock().unwrap() += 1;
+            async {
+              // The other chunk finds the credentials expired in the meantime.
+              tokio::time::sleep(Duration::from_millis(20)).await;
+              Ok(vec![(
+                String::from("Authorization"),
+                String::from("Bearer new"),
+              )])
+            }
+          }
+        })),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_with_token("new", Arc::clone(&tokens))),
+      None,
+    );
+
+    let urls = tts
+      .create_audio(String::from("bom dia. boa noite."))
+      .await?;
+
+    assert_eq!(2, urls.len());
+    assert_eq!(1, *refreshes.lock().unwrap());
+    assert_eq!(
+      vec!["Bearer old", "Bearer old", "Bearer new", "Bearer new"],
+      *tokens.lock().unwrap()
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn credentials_rejected_after_a_refresh_are_invalid() {
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+
+    let tts = Tts::new(
+      Config {
+        retries: Some(3),
+        auth_headers: vec![(String::from("Authorization"), String::from("Bearer old"))],
+        refresh_credentials: Some(CredentialsRefresher::new(|| async {
+          Ok(vec![(
+            String::from("Authorization"),
+            String::from("Bearer still-wrong"),
+          )])
+        })),
+        ..Config::default()
+      },
+      Arc::new(mock_http_client_with_token("new", Arc::clone(&tokens))),
+      None,
+    );
+
+    let err = tts.create_audio(String::from("olá")).await.unwrap_err();
+
+    assert_eq!(
+      Some(&TtsError::InvalidCredentials),
+      err.downcast_ref::<TtsError>()
+    );
+    // Not retried after the credentials were found to be invalid.
+    assert_eq!(
+      vec!["Bearer old", "Bearer still-wrong"],
+      *tokens.lock().unwrap()
+    );
+  }
+}
@@ -0,0 +1,55 @@
+//! Bundles of settings that trade api load for how fast messages are read.
+
+use std::time::Duration;
+
+use super::backoff::{Backoff, Jitter};
+
+/// Whether messages should be read as soon as possible or the tts api should be treated gently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+  /// Many requests at the same time, quick retries and polling.
+  #[default]
+  LowLatency,
+  /// One request at a time, slow retries and polling.
+  Gentle,
+}
+
+/// The settings that control how hard the tts api is hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuning {
+  /// The maximum number of chunks of a message being created at the same time.
+  pub max_concurrency: usize,
+  /// How many times a chunk is retried after the first attempt fails.
+  pub retries: u32,
+  /// How long to wait between retries.
+  pub backoff: Backoff,
+  /// How long to wait before asking the api again if the audio file is ready.
+  pub poll_interval: Duration,
+}
+
+impl Mode {
+  pub fn tuning(&self) -> Tuning {
+    match self {
+      Mode::LowLatency => Tuning {
+        max_concurrency: 8,
+        retries: 3,
+        backoff: Backoff {
+          initial: Duration::from_millis(100),
+          max: Duration::from_secs(1),
+          jitter: Jitter::default(),
+        },
+        poll_interval: Duration::from_millis(200),
+      },
+      Mode::Gentle => Tuning {
+        max_concurrency: 1,
+        retries: 5,
+        backoff: Backoff {
+          initial: Duration::from_secs(1),
+          max: Duration::from_secs(10),
+          jitter: Jitter::default(),
+        },
+        poll_interval: Duration::from_secs(1),
+      },
+    }
+  }
+}
@@ -0,0 +1,357 @@
+//! Just enough of the mp3 format to join audio files, create silence and change the volume.
+
+use std::time::Duration;
+
+/// Bitrates in kbps of MPEG-1 Layer III frames, indexed by the bitrate bits of the header.
+const MPEG1_BITRATES: [u32; 15] = [
+  0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+
+/// Bitrates in kbps of MPEG-2 and MPEG-2.5 Layer III frames.
+const MPEG2_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+  Mpeg1,
+  Mpeg2,
+  Mpeg25,
+}
+
+/// The header of a Layer III frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+  bytes: [u8; 4],
+  version: Version,
+  bitrate: u32,
+  sample_rate: u32,
+  padding: bool,
+}
+
+impl FrameHeader {
+  /// Returns `None` when `bytes` is not the header of a Layer III frame.
+  pub fn parse(bytes: [u8; 4]) -> Option<Self> {
+    // Frame sync: 11 bits set.
+    if bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+      return None;
+    }
+
+    let version = match (bytes[1] >> 3) & 0b11 {
+      0b00 => Version::Mpeg25,
+      0b10 => Version::Mpeg2,
+      0b11 => Version::Mpeg1,
+      _ => return None,
+    };
+
+    // Only Layer III.
+    if (bytes[1] >> 1) & 0b11 != 0b01 {
+      return None;
+    }
+
+    let bitrate_index = usize::from(bytes[2] >> 4);
+    let bitrate = match version {
+      Version::Mpeg1 => MPEG1_BITRATES.get(bitrate_index)?,
+      Version::Mpeg2 | Version::Mpeg25 => MPEG2_BITRATES.get(bitrate_index)?,
+    };
+    // Free format streams are not supported.
+    if *bitrate == 0 {
+      return None;
+    }
+
+    let sample_rates = match version {
+      Version::Mpeg1 => [44100, 48000, 32000],
+      Version::Mpeg2 => [22050, 24000, 16000],
+      Version::Mpeg25 => [11025, 12000, 8000],
+    };
+    let sample_rate = *sample_rates.get(usize::from((bytes[2] >> 2) & 0b11))?;
+
+    Some(Self {
+      bytes,
+      version,
+      bitrate: bitrate * 1000,
+      sample_rate,
+      padding: (bytes[2] >> 1) & 1 == 1,
+    })
+  }
+
+  pub fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  /// The number of samples of each channel in the frame.
+  pub fn samples(&self) -> u32 {
+    match self.version {
+      Version::Mpeg1 => 1152,
+      Version::Mpeg2 | Version::Mpeg25 => 576,
+    }
+  }
+
+  /// The length of the frame in bytes, including the header.
+  pub fn frame_len(&self) -> usize {
+    let len = self.samples() / 8 * self.bitrate / self.sample_rate;
+    (len + u32::from(self.padding)) as usize
+  }
+
+  pub fn duration(&self) -> Duration {
+    Duration::from_secs_f64(f64::from(self.samples()) / f64::from(self.sample_rate))
+  }
+
+  fn channels(&self) -> usize {
+    // Channel mode 0b11 is mono.
+    if self.bytes[3] >> 6 == 0b11 {
+      1
+    } else {
+      2
+    }
+  }
+}
+
+/// How much one step of `adjust_gain` changes the volume.
+pub const GAIN_STEP_DB: f32 = 1.5;
+
+/// Reads `len` bits starting at bit `offset` of `bytes`, most significant bit first.
+fn read_bits(bytes: &[u8], offset: usize, len: usize) -> u32 {
+  (offset..offset + len).fold(0, |value, bit| {
+    (value << 1) | u32::from((bytes[bit / 8] >> (7 - bit % 8)) & 1)
+  })
+}
+
+/// Writes the `len` lower bits of `value` starting at bit `offset` of `bytes`.
+fn write_bits(bytes: &mut [u8], offset: usize, len: usize, value: u32) {
+  for i in 0..len {
+    let bit = offset + i;
+    let mask = 1 << (7 - bit % 8);
+    if (value >> (len - 1 - i)) & 1 == 1 {
+      bytes[bit / 8] |= mask;
+    } else {
+      bytes[bit / 8] &= !mask;
+    }
+  }
+}
+
+/// Changes the global gain of every granule of the frame by `steps`.
+fn adjust_frame_gain(frame: &mut [u8], header: &FrameHeader, steps: i32) {
+  let channels = header.channels();
+  let crc_len = if header.bytes[1] & 1 == 0 { 2 } else { 0 };
+
+  // Where the first granule starts in the side information and how long each granule is.
+  let (granules, first_granule, granule_len, side_info_len) = match header.version {
+    Version::Mpeg1 => (
+      2,
+      9 + if channels == 1 { 5 } else { 3 } + 4 * channels,
+      59,
+      if channels == 1 { 17 } else { 32 },
+    ),
+    Version::Mpeg2 | Version::Mpeg25 => (1, 8 + channels, 63, if channels == 1 { 9 } else { 17 }),
+  };
+
+  let side_info = match frame.get_mut(4 + crc_len..4 + crc_len + side_info_len) {
+    None => return,
+    Some(side_info) => side_info,
+  };
+
+  for granule in 0..granules * channels {
+    let offset = first_granule + granule * granule_len;
+
+    // Granules without audio, like the ones of silence frames, are left alone.
+    if read_bits(side_info, offset, 12) == 0 {
+      continue;
+    }
+
+    // The global gain comes after the length of the data and the big values.
+    let gain = read_bits(side_info, offset + 21, 8) as i32;
+    write_bits(
+      side_info,
+      offset + 21,
+      8,
+      (gain + steps).clamp(0, 255) as u32,
+    );
+  }
+}
+
+/// Changes the volume of the audio by `steps` of `GAIN_STEP_DB` without decoding it,
+/// by changing the global gain of every frame. Bytes that are not frames are kept.
+pub fn adjust_gain(bytes: &[u8], steps: i32) -> Vec<u8> {
+  let mut adjusted = bytes.to_vec();
+
+  if steps == 0 {
+    return adjusted;
+  }
+
+  let mut i = bytes.len() - strip_id3(bytes).len();
+
+  while i + 4 <= adjusted.len() {
+    let header = FrameHeader::parse([
+      adjusted[i],
+      adjusted[i + 1],
+      adjusted[i + 2],
+      adjusted[i + 3],
+    ]);
+
+    match header {
+      Some(header) if i + header.frame_len() <= adjusted.len() => {
+        let frame_len = header.frame_len();
+        adjust_frame_gain(&mut adjusted[i..i + frame_len], &header, steps);
+        i += frame_len;
+      }
+      _ => i += 1,
+    }
+  }
+
+  adjusted
+}
+
+/// Returns `bytes` without the ID3v2 tag at the start of it, if there's one.
+pub fn strip_id3(bytes: &[u8]) -> &[u8] {
+  if bytes.len() < 10 || !bytes.starts_with(b"ID3") {
+    return bytes;
+  }
+
+  // The size is stored in 4 bytes that use only their lower 7 bits.
+  let size = bytes[6..10]
+    .iter()
+    .fold(0_usize, |size, byte| (size << 7) | usize::from(byte & 0x7F));
+
+  let has_footer = bytes[5] & 0x10 != 0;
+  let len = 10 + size + if has_footer { 10 } else { 0 };
+
+  &bytes[len.min(bytes.len())..]
+}
+
+/// Returns the header of the first frame in `bytes`.
+pub fn first_frame_header(bytes: &[u8]) -> Option<FrameHeader> {
+  strip_id3(bytes)
+    .windows(4)
+    .find_map(|window| FrameHeader::parse([window[0], window[1], window[2], window[3]]))
+}
+
+/// Returns frames of silence lasting at least `duration`, encoded like the frame of `header`
+/// so they can be joined with the file the header came from.
+pub fn silence(header: &FrameHeader, duration: Duration) -> Vec<u8> {
+  let frames = (duration.as_secs_f64() / header.duration().as_secs_f64()).ceil() as usize;
+
+  let mut frame_header = header.bytes;
+  // No CRC after the header.
+  frame_header[1] |= 0x01;
+  // No padding byte, every frame has the same length.
+  frame_header[2] &= !0x02;
+
+  let header = FrameHeader::parse(frame_header).unwrap_or(*header);
+
+  let mut frame = vec![0; header.frame_len()];
+  frame[..4].copy_from_slice(&header.bytes);
+
+  // A frame without side information or main data decodes to silence.
+  frame.repeat(frames)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_frame_header_parse() {
+    let header = FrameHeader::parse([0xFF, 0xFB, 0x90, 0x64]).unwrap();
+    assert_eq!(44100, header.sample_rate());
+    assert_eq!(1152, header.samples());
+    assert_eq!(417, header.frame_len());
+
+    // MPEG-2, 32 kbps, 24000 Hz.
+    let header = FrameHeader::parse([0xFF, 0xF3, 0x44, 0xC4]).unwrap();
+    assert_eq!(24000, header.sample_rate());
+    assert_eq!(576, header.samples());
+    assert_eq!(96, header.frame_len());
+
+    assert_eq!(None, FrameHeader::parse([0x49, 0x44, 0x33, 0x03]));
+    // Layer II.
+    assert_eq!(None, FrameHeader::parse([0xFF, 0xFD, 0x90, 0x64]));
+  }
+
+  #[test]
+  fn test_strip_id3() {
+    let tests: Vec<(&[u8], &[u8])> = vec![
+      (&[], &[]),
+      (&[0xFF, 0xFB, 0x90, 0x64], &[0xFF, 0xFB, 0x90, 0x64]),
+      (
+        &[
+          b'I', b'D', b'3', 3, 0, 0, 0, 0, 0, 0, 0xFF, 0xFB, 0x90, 0x64,
+        ],
+        &[0xFF, 0xFB, 0x90, 0x64],
+      ),
+      (
+        &[b'I', b'D', b'3', 3, 0, 0, 0, 0, 0, 2, b'x', b'y', 0xFF],
+        &[0xFF],
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, strip_id3(input), "input={:?}", input);
+    }
+  }
+
+  #[test]
+  fn test_read_and_write_bits() {
+    let mut bytes = vec![0; 3];
+
+    write_bits(&mut bytes, 5, 8, 0b1011_0111);
+    assert_eq!(vec![0b0000_0101, 0b1011_1000, 0], bytes);
+    assert_eq!(0b1011_0111, read_bits(&bytes, 5, 8));
+
+    write_bits(&mut bytes, 5, 8, 0);
+    assert_eq!(vec![0; 3], bytes);
+  }
+
+  #[test]
+  fn adjust_gain_changes_the_global_gain_of_each_granule() {
+    let header = FrameHeader::parse([0xFF, 0xFB, 0x90, 0x64]).unwrap();
+    assert_eq!(2, header.channels());
+
+    // Two frames of a stereo MPEG-1 file: 2 granules with 2 channels each.
+    let mut frame = vec![0; header.frame_len()];
+    frame[..4].copy_from_slice(&header.bytes);
+    let side_info = &mut frame[4..36];
+    for (granule, gain) in [140, 150, 160, 255].into_iter().enumerate() {
+      let offset = 9 + 3 + 8 + granule * 59;
+      write_bits(side_info, offset, 12, 100);
+      write_bits(side_info, offset + 21, 8, gain);
+    }
+    let file = [&[b'I', b'D', b'3', 3, 0, 0, 0, 0, 0, 0][..], &frame, &frame].concat();
+
+    let gains = |file: &[u8], frame: usize| -> Vec<u32> {
+      let side_info = &file[10 + frame * header.frame_len() + 4..];
+      (0..4)
+        .map(|granule| read_bits(side_info, 9 + 3 + 8 + granule * 59 + 21, 8))
+        .collect()
+    };
+
+    let louder = adjust_gain(&file, 3);
+    assert_eq!(file.len(), louder.len());
+    assert_eq!(vec![143, 153, 163, 255], gains(&louder, 0));
+    assert_eq!(vec![143, 153, 163, 255], gains(&louder, 1));
+
+    let quieter = adjust_gain(&file, -2);
+    assert_eq!(vec![138, 148, 158, 253], gains(&quieter, 1));
+
+    assert_eq!(file, adjust_gain(&file, 0));
+
+    // Silence has no audio to change.
+    let silence = silence(&header, Duration::from_millis(100));
+    assert_eq!(silence, adjust_gain(&silence, 5));
+  }
+
+  #[test]
+  fn silence_lasts_at_least_the_duration() {
+    let header = FrameHeader::parse([0xFF, 0xFA, 0x92, 0x64]).unwrap();
+
+    // Each frame lasts about 26ms.
+    let frames = silence(&header, Duration::from_millis(100));
+
+    assert_eq!(4 * 417, frames.len());
+    for frame in frames.chunks(417) {
+      // Without CRC and padding.
+      assert_eq!(&[0xFF, 0xFB, 0x90, 0x64], &frame[..4]);
+      assert!(frame[4..].iter().all(|byte| *byte == 0));
+    }
+
+    assert!(silence(&header, Duration::ZERO).is_empty());
+  }
+}
@@ -0,0 +1,1541 @@
+//! Transformations applied to a message before it is sent to the tts api.
+
+use std::{collections::HashMap, time::Duration};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{
+  acronyms, chunks, numbers, segments, BlockedWordPolicy, CommandPolicy, Config, MentionOnlyPolicy,
+  PunctuationPolicy, RepeatedWordPolicy, SpoilerPolicy,
+};
+
+/// Describes a non-fatal transformation applied to a message. The message is
+/// still read but it may not be exactly what the sender wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SynthesisWarning {
+  /// The message was longer than `Config::max_message_len` and had its end removed.
+  Truncated { original_len: usize, len: usize },
+  /// Words in `Config::blocked_words` were removed from the message.
+  WordsFiltered { count: usize },
+  /// Characters the voice can't read, like emoji, were removed from the message.
+  ScriptStripped { count: usize },
+  /// The message had more than `Config::max_sentences` sentences and `count` of them were not read.
+  SentencesDropped { count: usize },
+  /// Characters that are not in `Config::allowed_characters` were removed from the message.
+  CharactersNotAllowed { count: usize },
+  /// Creating audio for the message failed and `Config::fallback_audio` was returned instead.
+  FallbackAudio,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Preprocessed {
+  pub text: String,
+  pub warnings: Vec<SynthesisWarning>,
+}
+
+/// The only characters kept in messages when `Config::allowed_characters` is set, for
+/// events where nothing unexpected should be read. Whitespace is always kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedCharacters {
+  /// Letters of the latin alphabet, including accented ones like "ç" and "é".
+  pub latin_letters: bool,
+  pub digits: bool,
+  /// Any other character that is kept, like punctuation.
+  pub others: String,
+}
+
+impl Default for AllowedCharacters {
+  fn default() -> Self {
+    Self {
+      latin_letters: true,
+      digits: true,
+      others: String::from(".,!?:;'\"()-"),
+    }
+  }
+}
+
+impl AllowedCharacters {
+  pub fn allows(&self, character: char) -> bool {
+    let is_latin_letter = (character.is_alphabetic()
+      && (character.is_ascii()
+        || ('\u{00C0}'..='\u{024F}').contains(&character)
+        || ('\u{1E00}'..='\u{1EFF}').contains(&character)))
+      // Combining diacritical marks, the accent of "é" may be a separate character.
+      || ('\u{0300}'..='\u{036F}').contains(&character);
+
+    character.is_whitespace()
+      || (self.latin_letters && is_latin_letter)
+      || (self.digits && character.is_ascii_digit())
+      || self.others.contains(character)
+  }
+}
+
+/// Runs every transformation on `text`, a message read by `voice`, and keeps
+/// track of the ones that changed the message.
+pub fn preprocess(text: &str, config: &Config, voice: &str) -> Preprocessed {
+  let mut warnings = vec![];
+
+  let text = handle_commands(text, &config.command_prefixes, config.command_policy);
+
+  let text = handle_spoilers(&text, config.spoiler_policy);
+
+  let text = speak_emoji(&text, &config.emoji_names, config.max_emoji_repeats);
+
+  let text = speak_emoticons(&text, &config.emoticons);
+
+  let text = handle_mentions(&text, config.mention_only_policy);
+
+  let (text, count) = strip_unsupported_script(&text);
+  if count > 0 {
+    warnings.push(SynthesisWarning::ScriptStripped { count });
+  }
+
+  let text = replace_emotes(&text, &config.emotes);
+
+  // Before acronyms so a pronunciation can stop a word in capital letters from being spelled.
+  let text = apply_pronunciations(&text, &config.pronunciations);
+
+  let text = match config.max_word_repeats {
+    None => text,
+    Some(max_repeats) => collapse_repeated_words(&text, max_repeats, config.repeated_word_policy),
+  };
+
+  let text = verbalize(&text, config, voice);
+
+  // After numbers and currency are read as words so "R$" doesn't have to be allowed.
+  let text = match &config.allowed_characters {
+    None => text,
+    Some(allowed) => {
+      let (text, count) = remove_characters(&text, |character| allowed.allows(character));
+      if count > 0 {
+        warnings.push(SynthesisWarning::CharactersNotAllowed { count });
+      }
+      text
+    }
+  };
+
+  // Blocked words are kept when whole chunks are filtered after the message is divided.
+  let (text, count) = match config.blocked_word_policy {
+    BlockedWordPolicy::Remove | BlockedWordPolicy::Reject => {
+      filter_blocked_words(&text, &config.blocked_words, config.match_leetspeak)
+    }
+    BlockedWordPolicy::SkipChunk | BlockedWordPolicy::BleepChunk => (text, 0),
+  };
+  if count > 0 {
+    warnings.push(SynthesisWarning::WordsFiltered { count });
+  }
+
+  let text = match config.punctuation_policy {
+    PunctuationPolicy::Collapse => collapse_punctuation(&text),
+    PunctuationPolicy::Keep | PunctuationPolicy::Pause { .. } => text,
+  };
+
+  // Words replaced by other steps, like emoji names, leave a space before the punctuation.
+  let mut text = attach_punctuation(&normalize(&text));
+
+  if let Some(max_sentences) = config.max_sentences {
+    let sentences = chunks::split_sentences(&text);
+    if sentences.len() > max_sentences {
+      let count = sentences.len() - max_sentences;
+      let kept = sentences[..max_sentences].join(" ");
+      text = if config.mark_dropped_sentences {
+        format!(
+          "{}{}",
+          kept.trim_end_matches(|character: char| ".!?,;:".contains(character)),
+          config.truncation_ellipsis
+        )
+      } else {
+        kept
+      };
+      warnings.push(SynthesisWarning::SentencesDropped { count });
+    }
+  }
+
+  if let Some(max_message_len) = config.max_message_len {
+    let original_len = text.graphemes(true).count();
+    if original_len > max_message_len {
+      text = truncate(&text, max_message_len, &config.truncation_ellipsis);
+      warnings.push(SynthesisWarning::Truncated {
+        original_len,
+        len: text.graphemes(true).count(),
+      });
+    }
+  }
+
+  // Pauses are added last so the markup is not truncated or changed by other steps.
+  if let PunctuationPolicy::Pause { per_mark, max } = config.punctuation_policy {
+    text = punctuation_to_pauses(&text, per_mark, max);
+  }
+
+  Preprocessed { text, warnings }
+}
+
+/// Reads the emoji, pronunciations and numbers of text that's not written by viewers,
+/// like `Config::message_prefix`, without the steps that decide if and how much of
+/// a message is read.
+pub fn preprocess_fixed_text(text: &str, config: &Config, voice: &str) -> String {
+  let text = speak_emoji(text, &config.emoji_names, config.max_emoji_repeats);
+
+  let text = speak_emoticons(&text, &config.emoticons);
+
+  let text = apply_pronunciations(&text, &config.pronunciations);
+
+  let text = verbalize(&text, config, voice);
+
+  attach_punctuation(&normalize(&text))
+}
+
+/// Reads acronyms, currency, dates, times, math and numbers as words.
+fn verbalize(text: &str, config: &Config, voice: &str) -> String {
+  let text = if config.read_acronyms {
+    acronyms::verbalize_acronyms(text, &config.acronym_readings)
+  } else {
+    text.to_string()
+  };
+
+  let text = if config.read_currency {
+    numbers::verbalize_currency(&text, config.large_amounts)
+  } else {
+    text
+  };
+
+  // Before fractions so "dia 10/10" is read as a date.
+  let text = match config.date_order {
+    None => text,
+    Some(order) => numbers::verbalize_dates(&text, order),
+  };
+
+  let text = if config.expand_ordinals_and_fractions {
+    numbers::verbalize_ordinals_and_fractions(&text)
+  } else {
+    text
+  };
+
+  let text = if config.read_times {
+    numbers::verbalize_times(&text)
+  } else {
+    text
+  };
+
+  let text = match &config.math_words {
+    None => text,
+    Some(words) => numbers::verbalize_math(&text, words),
+  };
+
+  // Before decimals so long numbers with separators are not read in full.
+  let text = match config.long_number_threshold {
+    None => text,
+    Some(threshold) => numbers::replace_long_numbers(&text, threshold, config.long_number_policy),
+  };
+
+  match config.number_locale {
+    None => text,
+    Some(locale) => numbers::verbalize_decimals(&text, locale.for_voice(voice)),
+  }
+}
+
+/// Collapses consecutive whitespace into a single space and removes
+/// whitespace from the start and end of `text`.
+pub fn normalize(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Removes the space before punctuation that ends a word, "valeu fogo ?" becomes
+/// "valeu fogo?". Emoticons like ":)" are left alone.
+fn attach_punctuation(text: &str) -> String {
+  let mut buffer = String::with_capacity(text.len());
+  let mut rest = text;
+
+  while let Some(character) = rest.chars().next() {
+    rest = &rest[character.len_utf8()..];
+
+    if character == ' ' {
+      let after_punctuation =
+        rest.trim_start_matches(|character: char| ".,!?;:".contains(character));
+      let ends_word =
+        after_punctuation.is_empty() || after_punctuation.starts_with(char::is_whitespace);
+      if after_punctuation.len() < rest.len() && ends_word && !buffer.is_empty() {
+        continue;
+      }
+    }
+
+    buffer.push(character);
+  }
+
+  buffer
+}
+
+/// Handles the parts of the message between spoiler markers: "||spoiler||".
+/// A marker without a pair is removed and the text after it is read normally.
+fn handle_spoilers(text: &str, policy: SpoilerPolicy) -> String {
+  const MARKER: &str = "||";
+
+  let pieces: Vec<&str> = text.split(MARKER).collect();
+
+  // Pieces at odd positions are between a pair of markers, the last one is
+  // not when the number of markers is odd.
+  let last_spoiler = if pieces.len() % 2 == 0 {
+    pieces.len() - 2
+  } else {
+    pieces.len() - 1
+  };
+
+  let mut buffer = String::with_capacity(text.len());
+
+  for (i, piece) in pieces.into_iter().enumerate() {
+    if i % 2 == 0 || i > last_spoiler {
+      buffer.push_str(piece);
+      continue;
+    }
+
+    match policy {
+      SpoilerPolicy::Strip => buffer.push_str(piece),
+      SpoilerPolicy::Skip => buffer.push(' '),
+      SpoilerPolicy::Announce => {
+        buffer.push_str(" spoiler: ");
+        buffer.push_str(piece);
+        buffer.push(' ');
+      }
+    }
+  }
+
+  buffer
+}
+
+/// The emoji that are read by default, every other emoji is removed.
+pub fn default_emoji_names() -> HashMap<String, String> {
+  [
+    ("❤", "coração"),
+    ("💜", "coração"),
+    ("😂", "risos"),
+    ("🤣", "risos"),
+    ("😍", "apaixonado"),
+    ("😢", "triste"),
+    ("👍", "joinha"),
+    ("👏", "palmas"),
+    ("🔥", "fogo"),
+  ]
+  .into_iter()
+  .map(|(emoji, name)| (emoji.to_string(), name.to_string()))
+  .collect()
+}
+
+/// How text emoticons are read by default.
+pub fn default_emoticons() -> HashMap<String, String> {
+  [
+    (":)", "feliz"),
+    (":-)", "feliz"),
+    (":D", "feliz"),
+    ("=)", "feliz"),
+    (";)", "piscadinha"),
+    (":(", "triste"),
+    (":-(", "triste"),
+    (":'(", "chorando"),
+    (":P", "brincadeira"),
+    ("xD", "risos"),
+    ("XD", "risos"),
+    ("<3", "coração"),
+  ]
+  .into_iter()
+  .map(|(emoticon, phrase)| (emoticon.to_string(), phrase.to_string()))
+  .collect()
+}
+
+/// The chat emotes removed by default.
+pub fn default_emotes() -> HashMap<String, String> {
+  [
+    "PogChamp",
+    "Pog",
+    "Kappa",
+    "KEKW",
+    "LUL",
+    "LULW",
+    "OMEGALUL",
+    "monkaS",
+    "PepeHands",
+    "PepeLaugh",
+    "Sadge",
+    "BibleThump",
+    "ResidentSleeper",
+  ]
+  .into_iter()
+  .map(|emote| (emote.to_string(), String::new()))
+  .collect()
+}
+
+/// How brand names with their own spelling are read by default.
+pub fn default_pronunciations() -> HashMap<String, String> {
+  [
+    ("iPhone", "aifone"),
+    ("iPad", "aipad"),
+    ("eSports", "ispórts"),
+    ("YouTube", "iutúbi"),
+    ("WhatsApp", "uatsápi"),
+  ]
+  .into_iter()
+  .map(|(word, reading)| (word.to_string(), reading.to_string()))
+  .collect()
+}
+
+/// Replaces the words in `pronunciations` in `text` by how they are read. Words written
+/// with their own casing, like "iPhone", only match when written the same way, words
+/// written in lowercase match however they are written: "pix" matches "PIX" and "Pix".
+fn apply_pronunciations(text: &str, pronunciations: &HashMap<String, String>) -> String {
+  if pronunciations.is_empty() {
+    return text.to_string();
+  }
+
+  numbers::replace_words(text, |word| {
+    pronunciations
+      .get(word)
+      .or_else(|| pronunciations.get(&word.to_lowercase()))
+      .cloned()
+  })
+}
+
+/// Parses a list of emotes with one emote per line, "Kappa" removes the emote and
+/// "Kappa=piscadinha" reads it as "piscadinha". Empty lines and lines starting
+/// with "#" are ignored.
+pub fn parse_emotes(text: &str) -> HashMap<String, String> {
+  text
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(|line| match line.split_once('=') {
+      None => (line.to_string(), String::new()),
+      Some((emote, replacement)) => (emote.trim().to_string(), replacement.trim().to_string()),
+    })
+    .filter(|(emote, _)| !emote.is_empty())
+    .collect()
+}
+
+/// Replaces the emoji in `emoji_names` with their names.
+/// Longer emoji are matched first so sequences win over the emoji they contain.
+/// The same emoji repeated in a row is read at most `max_repeats` times.
+fn speak_emoji(
+  text: &str,
+  emoji_names: &HashMap<String, String>,
+  max_repeats: Option<usize>,
+) -> String {
+  // Variation selectors only change how the previous character is displayed,
+  // removing them allows "❤" to match "❤️".
+  let text = text.replace('\u{FE0F}', "");
+
+  let mut emojis: Vec<_> = emoji_names
+    .iter()
+    .filter(|(emoji, _)| !emoji.is_empty())
+    .collect();
+  emojis.sort_by_key(|(emoji, _)| std::cmp::Reverse(emoji.len()));
+
+  let mut buffer = String::with_capacity(text.len());
+  let mut rest = text.as_str();
+  // The last emoji read and how many times in a row it appeared.
+  let mut previous: Option<(&str, usize)> = None;
+
+  'outer: while let Some(character) = rest.chars().next() {
+    for (emoji, name) in emojis.iter() {
+      if rest.starts_with(emoji.as_str()) {
+        let repeats = match previous {
+          Some((previous_emoji, repeats)) if previous_emoji == emoji.as_str() => repeats + 1,
+          _ => 1,
+        };
+        previous = Some((emoji.as_str(), repeats));
+
+        if max_repeats.is_none_or(|max_repeats| repeats <= max_repeats) {
+          buffer.push(' ');
+          buffer.push_str(name);
+          buffer.push(' ');
+        }
+        rest = &rest[emoji.len()..];
+        continue 'outer;
+      }
+    }
+
+    // Whitespace between emoji doesn't break a repetition.
+    if !character.is_whitespace() {
+      previous = None;
+    }
+
+    buffer.push(character);
+    rest = &rest[character.len_utf8()..];
+  }
+
+  buffer
+}
+
+/// Replaces the emoticons in `emoticons` with their phrases, an empty phrase removes
+/// the emoticon. Only whole words are emoticons so the ":" and ")" in "horário: 10h
+/// (manhã)" are left alone.
+fn speak_emoticons(text: &str, emoticons: &HashMap<String, String>) -> String {
+  if emoticons.is_empty() {
+    return text.to_string();
+  }
+
+  text
+    .split_whitespace()
+    .filter_map(|word| match emoticons.get(word) {
+      None => Some(word),
+      Some(phrase) if phrase.is_empty() => None,
+      Some(phrase) => Some(phrase.as_str()),
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Returns true when the voice knows how to read `character`.
+fn is_supported_character(character: char) -> bool {
+  character.is_ascii()
+    // Latin-1 Supplement and Latin Extended-A/B letters: á, ç, õ...
+    || ('\u{00C0}'..='\u{024F}').contains(&character)
+    // Combining diacritical marks.
+    || ('\u{0300}'..='\u{036F}').contains(&character)
+    // Latin Extended Additional.
+    || ('\u{1E00}'..='\u{1EFF}').contains(&character)
+    || character.is_whitespace()
+    || "ºª°¡¿«»“”‘’–—…€£".contains(character)
+}
+
+/// Removes characters the voice can't read, like emoji and non latin
+/// scripts, and returns how many characters were removed.
+fn strip_unsupported_script(text: &str) -> (String, usize) {
+  remove_characters(text, is_supported_character)
+}
+
+/// Removes the characters `keep` returns false for and returns how many were removed.
+fn remove_characters(text: &str, keep: impl Fn(char) -> bool) -> (String, usize) {
+  let mut removed = 0;
+
+  let text = text
+    .chars()
+    .filter(|character| {
+      let kept = keep(*character);
+      if !kept {
+        removed += 1;
+      }
+      kept
+    })
+    .collect();
+
+  (text, removed)
+}
+
+/// Returns the name mentioned by `word`, "@fulano," mentions "fulano".
+fn mentioned_name(word: &str) -> Option<&str> {
+  let name = word
+    .strip_prefix('@')?
+    .trim_end_matches(|character: char| !character.is_alphanumeric() && character != '_');
+
+  let is_name = !name.is_empty()
+    && name
+      .chars()
+      .all(|character| character.is_alphanumeric() || character == '_');
+
+  is_name.then_some(name)
+}
+
+/// Handles messages whose first word is a prefix followed by a letter or digit,
+/// like "!play", according to `policy`. Other messages are returned as they are.
+fn handle_commands(text: &str, prefixes: &[String], policy: CommandPolicy) -> String {
+  let trimmed = text.trim_start();
+  let (command, rest) = trimmed
+    .split_once(char::is_whitespace)
+    .unwrap_or((trimmed, ""));
+
+  let is_command = prefixes
+    .iter()
+    .filter(|prefix| !prefix.is_empty())
+    .any(|prefix| {
+      command
+        .strip_prefix(prefix.as_str())
+        .and_then(|name| name.chars().next())
+        .is_some_and(char::is_alphanumeric)
+    });
+
+  if !is_command {
+    return text.to_string();
+  }
+
+  match policy {
+    CommandPolicy::Skip => String::new(),
+    CommandPolicy::Strip => rest.trim_start().to_string(),
+  }
+}
+
+/// Removes the "@" of mentions so only the name is read. Messages that only
+/// mention people are handled according to `policy`.
+fn handle_mentions(text: &str, policy: MentionOnlyPolicy) -> String {
+  let words: Vec<&str> = text.split_whitespace().collect();
+  let names: Vec<&str> = words
+    .iter()
+    .filter_map(|word| mentioned_name(word))
+    .collect();
+
+  if !names.is_empty() && names.len() == words.len() {
+    return match policy {
+      MentionOnlyPolicy::Skip => String::new(),
+      MentionOnlyPolicy::Announce => {
+        let (last, rest) = names.split_last().unwrap();
+        if rest.is_empty() {
+          format!("mencionou {}", last)
+        } else {
+          format!("mencionou {} e {}", rest.join(", "), last)
+        }
+      }
+    };
+  }
+
+  words
+    .iter()
+    .map(|word| match mentioned_name(word) {
+      None => word,
+      Some(_) => &word[1..],
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Replaces the words in `emotes` in `text` by how they are read, emotes read as an
+/// empty string are removed with the punctuation around them. Emotes are case
+/// sensitive, "LUL" is an emote but "lul" is not.
+fn replace_emotes(text: &str, emotes: &HashMap<String, String>) -> String {
+  if emotes.is_empty() {
+    return text.to_string();
+  }
+
+  text
+    .split_whitespace()
+    .filter_map(|word| {
+      let emote = word.trim_matches(|character: char| !character.is_alphanumeric());
+
+      match emotes.get(emote) {
+        None => Some(word.to_string()),
+        Some(replacement) if replacement.is_empty() => None,
+        // Keeps the punctuation around the emote: "Kappa!" is read as "piscadinha!".
+        Some(replacement) => Some(word.replacen(emote, replacement, 1)),
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Handles words repeated in a row more than `max_repeats` times according to `policy`.
+/// Words are compared without the punctuation around them and ignoring case, accents
+/// are kept: "é" and "e" are different words. The punctuation after the last repetition is kept.
+fn collapse_repeated_words(text: &str, max_repeats: usize, policy: RepeatedWordPolicy) -> String {
+  let is_not_alphanumeric = |character: char| !character.is_alphanumeric();
+  let key = |word: &str| word.trim_matches(is_not_alphanumeric).to_lowercase();
+
+  let words: Vec<&str> = text.split_whitespace().collect();
+  let max_repeats = max_repeats.max(1);
+
+  let mut result = Vec::with_capacity(words.len());
+
+  let mut i = 0;
+  while i < words.len() {
+    let word = key(words[i]);
+
+    let mut end = i + 1;
+    if !word.is_empty() {
+      while end < words.len() && key(words[end]) == word {
+        end += 1;
+      }
+    }
+
+    let run = &words[i..end];
+    let last = run[run.len() - 1];
+
+    if run.len() <= max_repeats {
+      result.extend(run.iter().map(|word| word.to_string()));
+    } else {
+      match policy {
+        RepeatedWordPolicy::Limit => {
+          result.extend(run[..max_repeats - 1].iter().map(|word| word.to_string()));
+          result.push(last.to_string());
+        }
+        RepeatedWordPolicy::Announce => {
+          let trailing = &last[last.trim_end_matches(is_not_alphanumeric).len()..];
+          result.push(format!(
+            "{} várias vezes{}",
+            run[0].trim_end_matches(is_not_alphanumeric),
+            trailing
+          ));
+        }
+      }
+    }
+
+    i = end;
+  }
+
+  result.join(" ")
+}
+
+/// Collapses repeated exclamation and question marks, "!!!" is read as "!"
+/// and "?!?!" as "?!". Runs of more than three dots become "...".
+fn collapse_punctuation(text: &str) -> String {
+  let mut buffer = String::with_capacity(text.len());
+  let mut run = String::new();
+  let mut dots = 0;
+
+  let flush = |buffer: &mut String, run: &mut String, dots: &mut usize| {
+    buffer.push_str(run);
+    run.clear();
+    for _ in 0..(*dots).min(3) {
+      buffer.push('.');
+    }
+    *dots = 0;
+  };
+
+  for character in text.chars() {
+    match character {
+      '!' | '?' => {
+        if dots > 0 {
+          flush(&mut buffer, &mut run, &mut dots);
+        }
+        if !run.contains(character) {
+          run.push(character);
+        }
+      }
+      '.' => {
+        if !run.is_empty() {
+          flush(&mut buffer, &mut run, &mut dots);
+        }
+        dots += 1;
+      }
+      _ => {
+        flush(&mut buffer, &mut run, &mut dots);
+        buffer.push(character);
+      }
+    }
+  }
+
+  flush(&mut buffer, &mut run, &mut dots);
+
+  buffer
+}
+
+/// Returns how long the pause for a run of `len` punctuation marks is.
+fn pause_duration(len: usize, per_mark: Duration, max: Duration) -> Duration {
+  per_mark
+    .saturating_mul(u32::try_from(len).unwrap_or(u32::MAX))
+    .min(max)
+}
+
+/// Replaces runs of two or more terminal punctuation marks with the first mark of
+/// the run followed by a pause: "então......" becomes "então. [pause=1200]".
+fn punctuation_to_pauses(text: &str, per_mark: Duration, max: Duration) -> String {
+  let mut buffer = String::with_capacity(text.len());
+  let mut chars = text.chars().peekable();
+
+  while let Some(character) = chars.next() {
+    buffer.push(character);
+
+    if !matches!(character, '.' | '!' | '?') {
+      continue;
+    }
+
+    let mut len = 1;
+    while let Some('.' | '!' | '?') = chars.peek() {
+      chars.next();
+      len += 1;
+    }
+
+    if len > 1 {
+      buffer.push(' ');
+      buffer.push_str(&segments::pause_tag(pause_duration(len, per_mark, max)));
+      buffer.push(' ');
+    }
+  }
+
+  normalize(&buffer)
+}
+
+/// Removes the words in `blocked_words` from `text`. Words are compared
+/// ignoring case and the punctuation around them.
+fn filter_blocked_words(text: &str, blocked_words: &[String], leetspeak: bool) -> (String, usize) {
+  if blocked_words.is_empty() {
+    return (text.to_string(), 0);
+  }
+
+  let mut removed = 0;
+
+  let text = text
+    .split_whitespace()
+    .filter(|word| {
+      let blocked = is_blocked(word, blocked_words, leetspeak);
+      if blocked {
+        removed += 1;
+      }
+      !blocked
+    })
+    .collect::<Vec<_>>()
+    .join(" ");
+
+  (text, removed)
+}
+
+/// Replaces digits and symbols used in place of letters, "p0rr@" becomes "porra".
+fn undo_leetspeak(word: &str) -> String {
+  word
+    .chars()
+    .map(|character| match character {
+      '0' => 'o',
+      '1' => 'i',
+      '3' => 'e',
+      '4' | '@' => 'a',
+      '5' | '$' => 's',
+      '7' => 't',
+      character => character,
+    })
+    .collect()
+}
+
+/// When `leetspeak` is set, "m3rd@" matches "merda" too.
+fn is_blocked(word: &str, blocked_words: &[String], leetspeak: bool) -> bool {
+  let normalize = |word: &str| {
+    word
+      .trim_matches(|character: char| !character.is_alphanumeric())
+      .to_lowercase()
+  };
+
+  let mut candidates = vec![normalize(word)];
+  if leetspeak {
+    candidates.push(normalize(&undo_leetspeak(word)));
+  }
+
+  blocked_words.iter().any(|blocked_word| {
+    let blocked_word = blocked_word.to_lowercase();
+    candidates
+      .iter()
+      .any(|candidate| *candidate == blocked_word)
+  })
+}
+
+/// Returns the first word of `text` that is in `blocked_words`.
+pub fn find_blocked_word<'a>(
+  text: &'a str,
+  blocked_words: &[String],
+  leetspeak: bool,
+) -> Option<&'a str> {
+  text
+    .split_whitespace()
+    .find(|word| is_blocked(word, blocked_words, leetspeak))
+}
+
+/// Keeps at most `max_len` graphemes of `text`, counting the `ellipsis` appended to it.
+/// The text is cut at the last word boundary before the limit, unless the first
+/// word alone is longer than the limit.
+fn truncate(text: &str, max_len: usize, ellipsis: &str) -> String {
+  if text.graphemes(true).count() <= max_len {
+    return text.to_string();
+  }
+
+  let keep = max_len.saturating_sub(ellipsis.graphemes(true).count());
+
+  // SAFETY: the text has more than `max_len` graphemes.
+  let (end, _) = text.grapheme_indices(true).nth(keep).unwrap();
+
+  let mut cut = &text[..end];
+
+  // Cutting right before whitespace doesn't split a word.
+  if !text[end..].starts_with(char::is_whitespace) {
+    if let Some(word_start) = cut.rfind(char::is_whitespace) {
+      cut = &cut[..word_start];
+    }
+  }
+
+  let cut =
+    cut.trim_end_matches(|character: char| character.is_whitespace() || ",;:".contains(character));
+
+  format!("{}{}", cut, ellipsis)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_normalize() {
+    let tests = vec![
+      ("", ""),
+      ("   ", ""),
+      ("olá", "olá"),
+      ("  olá   pessoal \n tudo bem?  ", "olá pessoal tudo bem?"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, normalize(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn test_attach_punctuation() {
+    let tests = vec![
+      ("", ""),
+      ("olá", "olá"),
+      ("valeu fogo ?", "valeu fogo?"),
+      (
+        "espera ... e aí , tudo bem ?!",
+        "espera... e aí, tudo bem?!",
+      ),
+      ("? começo", "? começo"),
+      ("oi :) tchau", "oi :) tchau"),
+      ("oi :)", "oi :)"),
+      ("- 3 ovos", "- 3 ovos"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, attach_punctuation(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn only_allowed_characters_are_kept() {
+    let allowed = AllowedCharacters::default();
+
+    let tests = vec![
+      ("", ("", 0)),
+      ("olá, ação! 123", ("olá, ação! 123", 0)),
+      ("e\u{301} ß", ("e\u{301} ß", 0)),
+      ("<b>oi</b> #top", ("boib top", 6)),
+      ("привет oi", (" oi", 6)),
+    ];
+
+    for (input, (expected_text, expected_count)) in tests {
+      assert_eq!(
+        (expected_text.to_string(), expected_count),
+        remove_characters(input, |character| allowed.allows(character)),
+        "input={}",
+        input
+      );
+    }
+
+    let config = Config {
+      allowed_characters: Some(allowed),
+      ..Config::default()
+    };
+    assert_eq!(
+      Preprocessed {
+        text: String::from("doei dez reais, valeu pessoal"),
+        warnings: vec![SynthesisWarning::CharactersNotAllowed { count: 4 }],
+      },
+      preprocess("doei R$ 10, valeu ~~ pessoal ##", &config, "pt-BR")
+    );
+  }
+
+  #[test]
+  fn only_the_first_sentences_are_kept() {
+    let message = "Primeira frase. Segunda frase! Terceira? Quarta. Quinta. Sexta";
+
+    let tests = vec![
+      (None, false, message),
+      (Some(6), false, message),
+      (Some(2), false, "Primeira frase. Segunda frase!"),
+      (Some(2), true, "Primeira frase. Segunda frase…"),
+      (Some(0), false, ""),
+    ];
+
+    for (max_sentences, mark_dropped_sentences, expected) in tests {
+      let config = Config {
+        max_sentences,
+        mark_dropped_sentences,
+        ..Config::default()
+      };
+      assert_eq!(
+        expected,
+        preprocess(message, &config, "pt-BR").text,
+        "max_sentences={:?} mark_dropped_sentences={}",
+        max_sentences,
+        mark_dropped_sentences
+      );
+    }
+
+    let config = Config {
+      max_sentences: Some(2),
+      ..Config::default()
+    };
+    assert_eq!(
+      vec![SynthesisWarning::SentencesDropped { count: 4 }],
+      preprocess(message, &config, "pt-BR").warnings
+    );
+  }
+
+  #[test]
+  fn test_strip_unsupported_script() {
+    let tests = vec![
+      ("", ("", 0)),
+      ("ação à noite, 1º lugar!", ("ação à noite, 1º lugar!", 0)),
+      ("valeu 🎉🎉", ("valeu ", 2)),
+      ("привет oi", (" oi", 6)),
+    ];
+
+    for (input, (expected_text, expected_count)) in tests {
+      assert_eq!(
+        (expected_text.to_string(), expected_count),
+        strip_unsupported_script(input),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_speak_emoji() {
+    let emoji_names = default_emoji_names();
+
+    let tests = vec![
+      ("", ""),
+      ("olá", "olá"),
+      ("te amo❤️", "te amo coração "),
+      ("kkkk 😂😂", "kkkk  risos  risos "),
+      ("boa noite 🌙", "boa noite 🌙"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        speak_emoji(input, &emoji_names, None),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn limits_repeated_emoji() {
+    let emoji_names = default_emoji_names();
+
+    let tests = vec![
+      ("😂😂😂", Some(1), " risos "),
+      ("😂 😂 😂", Some(2), " risos   risos  "),
+      ("😂😂🔥🔥", Some(1), " risos  fogo "),
+      ("😂 kkk 😂", Some(1), " risos  kkk  risos "),
+      ("😂😂", Some(0), ""),
+    ];
+
+    for (input, max_repeats, expected) in tests {
+      assert_eq!(
+        expected,
+        speak_emoji(input, &emoji_names, max_repeats),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_handle_commands() {
+    let prefixes = vec![String::from("!"), String::from("/")];
+
+    let tests = vec![
+      (CommandPolicy::Skip, "!play música", ""),
+      (CommandPolicy::Strip, "!play música", "música"),
+      (CommandPolicy::Strip, "  /sr  música boa", "música boa"),
+      (CommandPolicy::Strip, "!skip", ""),
+      // Not commands.
+      (CommandPolicy::Skip, "", ""),
+      (CommandPolicy::Skip, "!!! que isso", "!!! que isso"),
+      (CommandPolicy::Skip, "olá !play", "olá !play"),
+      (CommandPolicy::Skip, "#play música", "#play música"),
+    ];
+
+    for (policy, input, expected) in tests {
+      assert_eq!(
+        expected,
+        handle_commands(input, &prefixes, policy),
+        "input={} policy={:?}",
+        input,
+        policy
+      );
+    }
+
+    // Without prefixes nothing is a command.
+    assert_eq!(
+      "!play música",
+      handle_commands("!play música", &[], CommandPolicy::Skip)
+    );
+  }
+
+  #[test]
+  fn test_handle_mentions() {
+    let tests = vec![
+      (MentionOnlyPolicy::Skip, "", ""),
+      (MentionOnlyPolicy::Skip, "valeu @fulano!", "valeu fulano!"),
+      (MentionOnlyPolicy::Skip, "@streamer @mod", ""),
+      (
+        MentionOnlyPolicy::Announce,
+        "@streamer @mod",
+        "mencionou streamer e mod",
+      ),
+      (
+        MentionOnlyPolicy::Announce,
+        "@a, @b_2 @c!",
+        "mencionou a, b_2 e c",
+      ),
+      (
+        MentionOnlyPolicy::Announce,
+        "@streamer",
+        "mencionou streamer",
+      ),
+      // Not mentions.
+      (MentionOnlyPolicy::Skip, "@ e-mail@x.com", "@ e-mail@x.com"),
+    ];
+
+    for (policy, input, expected) in tests {
+      assert_eq!(
+        expected,
+        handle_mentions(input, policy),
+        "input={} policy={:?}",
+        input,
+        policy
+      );
+    }
+  }
+
+  #[test]
+  fn test_replace_emotes() {
+    let mut emotes = default_emotes();
+    emotes.insert(String::from("Kappa"), String::from("piscadinha"));
+    emotes.insert(String::from("LULW"), String::from("risos"));
+
+    let tests = vec![
+      ("", ""),
+      ("olá pessoal", "olá pessoal"),
+      ("PogChamp obrigado", "obrigado"),
+      ("KEKW, lul LUL!", "lul"),
+      ("Kappa! que jogada LULW", "piscadinha! que jogada risos"),
+      ("Kappas não é emote", "Kappas não é emote"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, replace_emotes(input, &emotes), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn test_apply_pronunciations() {
+    let mut pronunciations = default_pronunciations();
+    pronunciations.insert(String::from("pix"), String::from("píquis"));
+
+    let tests = vec![
+      ("", ""),
+      ("comprei um iPhone!", "comprei um aifone!"),
+      // Words with their own casing only match when written the same way.
+      ("comprei um IPHONE", "comprei um IPHONE"),
+      ("comprei um iphone", "comprei um iphone"),
+      (
+        "manda um PIX, Pix ou pix.",
+        "manda um píquis, píquis ou píquis.",
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        apply_pronunciations(input, &pronunciations),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn brand_names_are_not_lowercased_with_words_in_capital_letters() {
+    let config = Config {
+      pronunciations: HashMap::new(),
+      read_acronyms: true,
+      ..Config::default()
+    };
+    assert_eq!(
+      "comprei um iPhone na nasa",
+      preprocess("comprei um iPhone na NASA", &config, "pt-BR").text
+    );
+
+    let config = Config {
+      read_acronyms: true,
+      ..Config::default()
+    };
+    assert_eq!(
+      "comprei um aifone na nasa",
+      preprocess("comprei um iPhone na NASA", &config, "pt-BR").text
+    );
+  }
+
+  #[test]
+  fn test_parse_emotes() {
+    let emotes = parse_emotes(
+      "
+      # Twitch
+      Kappa = piscadinha
+      PogChamp
+
+      LULW=risos
+      =nada
+      ",
+    );
+
+    assert_eq!(
+      HashMap::from([
+        (String::from("Kappa"), String::from("piscadinha")),
+        (String::from("PogChamp"), String::new()),
+        (String::from("LULW"), String::from("risos")),
+      ]),
+      emotes
+    );
+  }
+
+  #[test]
+  fn test_speak_emoticons() {
+    let mut emoticons = default_emoticons();
+    emoticons.insert(String::from(":/"), String::new());
+
+    let tests = vec![
+      ("", ""),
+      ("valeu :)", "valeu feliz"),
+      ("perdi :( :D", "perdi triste feliz"),
+      ("hmm :/ sei lá", "hmm sei lá"),
+      ("(parênteses)", "(parênteses)"),
+      ("horário: 10:30 (manhã)", "horário: 10:30 (manhã)"),
+      ("obrigado:)", "obrigado:)"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        speak_emoticons(input, &emoticons),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_collapse_repeated_words() {
+    let tests = vec![
+      ("", RepeatedWordPolicy::Limit, ""),
+      (
+        "obrigado obrigado obrigado",
+        RepeatedWordPolicy::Limit,
+        "obrigado obrigado obrigado",
+      ),
+      (
+        "obrigado Obrigado OBRIGADO obrigado obrigado!!",
+        RepeatedWordPolicy::Limit,
+        "obrigado Obrigado obrigado!!",
+      ),
+      (
+        "valeu, obrigado obrigado, obrigado obrigado demais",
+        RepeatedWordPolicy::Announce,
+        "valeu, obrigado várias vezes demais",
+      ),
+      (
+        "gol gol gol gol!",
+        RepeatedWordPolicy::Announce,
+        "gol várias vezes!",
+      ),
+      // "é" and "e" are different words.
+      ("é e é e é e", RepeatedWordPolicy::Limit, "é e é e é e"),
+      ("é é é é", RepeatedWordPolicy::Limit, "é é é"),
+      // Punctuation alone is not a word.
+      ("! ! ! !", RepeatedWordPolicy::Limit, "! ! ! !"),
+    ];
+
+    for (input, policy, expected) in tests {
+      assert_eq!(
+        expected,
+        collapse_repeated_words(input, 3, policy),
+        "input={} policy={:?}",
+        input,
+        policy
+      );
+    }
+  }
+
+  #[test]
+  fn test_collapse_punctuation() {
+    let tests = vec![
+      ("", ""),
+      ("obrigado!", "obrigado!"),
+      ("obrigado!!!", "obrigado!"),
+      ("sério?!?!?!", "sério?!"),
+      ("sério???", "sério?"),
+      ("então.....", "então..."),
+      ("então... ok", "então... ok"),
+      ("oi!!! tudo bem???", "oi! tudo bem?"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, collapse_punctuation(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn reads_mapped_emoji_and_strips_unmapped_ones() {
+    let config = Config::default();
+
+    assert_eq!(
+      Preprocessed {
+        text: String::from("amei coração valeu"),
+        warnings: vec![SynthesisWarning::ScriptStripped { count: 1 }],
+      },
+      preprocess("amei ❤️🎉 valeu", &config, "pt-BR")
+    );
+  }
+
+  #[test]
+  fn test_filter_blocked_words() {
+    let blocked_words = vec![String::from("porra"), String::from("Merda")];
+
+    let tests = vec![
+      ("", ("", 0)),
+      ("olá pessoal", ("olá pessoal", 0)),
+      ("que PORRA é essa, merda!", ("que é essa,", 2)),
+    ];
+
+    for (input, (expected_text, expected_count)) in tests {
+      assert_eq!(
+        (expected_text.to_string(), expected_count),
+        filter_blocked_words(input, &blocked_words, false),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_find_blocked_word() {
+    let blocked_words = vec![String::from("porra")];
+
+    assert_eq!(
+      None,
+      find_blocked_word("olá pessoal", &blocked_words, false)
+    );
+    assert_eq!(
+      Some("Porra!"),
+      find_blocked_word("olá pessoal, Porra!", &blocked_words, false)
+    );
+    assert_eq!(None, find_blocked_word("olá p0rr@!", &blocked_words, false));
+    assert_eq!(
+      Some("p0rr@!"),
+      find_blocked_word("olá p0rr@!", &blocked_words, true)
+    );
+  }
+
+  #[test]
+  fn blocked_words_written_in_leetspeak_are_caught() {
+    let blocked_words = vec![String::from("porra"), String::from("merda")];
+
+    let tests = vec![
+      ("que p0rra", ("que", 1)),
+      ("m3rda!", ("", 1)),
+      ("P0RR4 de M3RD4", ("de", 2)),
+      ("$3nh@ p0rr@", ("$3nh@", 1)),
+      // Only blocked words change, the rest is read as written.
+      ("nota 10, top 3", ("nota 10, top 3", 0)),
+    ];
+
+    for (input, (expected_text, expected_count)) in tests {
+      assert_eq!(
+        (expected_text.to_string(), expected_count),
+        filter_blocked_words(input, &blocked_words, true),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_preprocess() {
+    let config = Config {
+      max_message_len: Some(10),
+      blocked_words: vec![String::from("porra")],
+      ..Config::default()
+    };
+
+    assert_eq!(
+      Preprocessed {
+        text: String::from("olá pessoa"),
+        warnings: vec![]
+      },
+      preprocess("olá pessoa", &config, "pt-BR")
+    );
+
+    assert_eq!(
+      Preprocessed {
+        text: String::from("boa noite…"),
+        warnings: vec![
+          SynthesisWarning::ScriptStripped { count: 1 },
+          SynthesisWarning::WordsFiltered { count: 1 },
+          SynthesisWarning::Truncated {
+            original_len: 16,
+            len: 10
+          },
+        ]
+      },
+      preprocess("boa noite 🌙 porra galera", &config, "pt-BR")
+    );
+  }
+
+  #[test]
+  fn long_numbers_are_not_read_as_decimals() {
+    let config = Config {
+      long_number_threshold: Some(12),
+      ..Config::default()
+    };
+
+    let tests = vec![
+      ("pix 1.234.567.890.123", "pix número longo"),
+      (
+        "são 1.234,56",
+        "são mil duzentos e trinta e quatro vírgula cinquenta e seis",
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        preprocess(input, &config, "pt-BR").text,
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn keeps_the_words_of_messages_with_emotes_and_emoji_spam() {
+    let config = Config::default();
+
+    let tests = vec![
+      ("PogChamp obrigado!!! 🎉🎉🎉", "obrigado!"),
+      ("KEKW 😂😂😂😂 muito bom!!!", "risos muito bom!"),
+      ("Kappa valeu 🔥🔥🔥??", "valeu fogo?"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        preprocess(input, &config, "pt-BR").text,
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_handle_spoilers() {
+    let tests = vec![
+      ("", SpoilerPolicy::Skip, ""),
+      ("sem spoiler", SpoilerPolicy::Skip, "sem spoiler"),
+      (
+        "o final: ||ele morre|| kkk",
+        SpoilerPolicy::Skip,
+        "o final: kkk",
+      ),
+      (
+        "o final: ||ele morre|| kkk",
+        SpoilerPolicy::Strip,
+        "o final: ele morre kkk",
+      ),
+      (
+        "o final: ||ele morre|| kkk",
+        SpoilerPolicy::Announce,
+        "o final: spoiler: ele morre kkk",
+      ),
+      ("||a|| b ||c||", SpoilerPolicy::Skip, "b"),
+      // Unbalanced markers.
+      ("a ||b|| c ||d", SpoilerPolicy::Skip, "a c d"),
+      ("a || b", SpoilerPolicy::Announce, "a b"),
+    ];
+
+    for (input, policy, expected) in tests {
+      assert_eq!(
+        expected,
+        normalize(&handle_spoilers(input, policy)),
+        "input={} policy={:?}",
+        input,
+        policy
+      );
+    }
+  }
+
+  #[test]
+  fn pauses_grow_with_the_punctuation_run_up_to_the_max() {
+    let per_mark = Duration::from_millis(200);
+    let max = Duration::from_millis(1000);
+
+    let tests = vec![(1, 200), (2, 400), (3, 600), (5, 1000), (20, 1000)];
+
+    for (len, expected) in tests {
+      assert_eq!(
+        Duration::from_millis(expected),
+        pause_duration(len, per_mark, max),
+        "len={}",
+        len
+      );
+    }
+
+    let tests = vec![
+      ("fim.", "fim."),
+      ("então.. ok", "então. [pause=400] ok"),
+      ("então...... ok", "então. [pause=1000] ok"),
+      ("sério?!? sim", "sério? [pause=600] sim"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        punctuation_to_pauses(input, per_mark, max),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn pause_policy_adds_pauses_instead_of_collapsing_punctuation() {
+    let config = Config {
+      punctuation_policy: PunctuationPolicy::Pause {
+        per_mark: Duration::from_millis(200),
+        max: Duration::from_millis(1000),
+      },
+      ..Config::default()
+    };
+
+    assert_eq!(
+      "e o ganhador é. [pause=1000] você!",
+      preprocess("e o ganhador é...... você!", &config, "pt-BR").text
+    );
+  }
+
+  #[test]
+  fn test_truncate() {
+    let tests = vec![
+      ("olá pessoal", 20, "…", "olá pessoal"),
+      ("olá pessoal", 11, "…", "olá pessoal"),
+      ("olá pessoal tudo bem", 16, "…", "olá pessoal…"),
+      ("olá pessoal, tudo bem", 15, "…", "olá pessoal…"),
+      ("olá pessoal tudo bem", 16, "...", "olá pessoal..."),
+      ("olá pessoal tudo bem", 16, "", "olá pessoal tudo"),
+      // The first word doesn't fit.
+      ("paralelepípedo", 6, "…", "paral…"),
+      // "e\u{301}" is a single grapheme made of two characters.
+      (
+        "cafe\u{301} cafe\u{301} cafe\u{301}",
+        10,
+        "…",
+        "cafe\u{301} cafe\u{301}…",
+      ),
+      ("cafe\u{301}cafe\u{301}", 5, "…", "cafe\u{301}…"),
+    ];
+
+    for (input, max_len, ellipsis, expected) in tests {
+      let truncated = truncate(input, max_len, ellipsis);
+
+      assert_eq!(expected, truncated, "input={}", input);
+      assert!(
+        truncated.graphemes(true).count() <= max_len,
+        "input={}",
+        input
+      );
+    }
+  }
+}
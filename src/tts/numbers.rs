@@ -0,0 +1,1347 @@
+//! Writes numbers the way they are read in brazilian portuguese, the voice
+//! reads "1º" and "1/2" character by character otherwise.
+
+/// What to do with numbers that have too many digits to be read, like transaction ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LongNumberPolicy {
+  /// Reads "número longo" instead of the number.
+  #[default]
+  Announce,
+  /// Removes the number from the message.
+  Skip,
+}
+
+/// Amounts of money above `threshold` reais are read in a short form so a comically
+/// large donation doesn't take half a minute to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LargeAmounts {
+  pub threshold: u64,
+  pub style: LargeAmountStyle,
+}
+
+impl Default for LargeAmounts {
+  fn default() -> Self {
+    Self {
+      threshold: 1_000_000,
+      style: LargeAmountStyle::default(),
+    }
+  }
+}
+
+/// How amounts above `LargeAmounts::threshold` are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LargeAmountStyle {
+  /// Rounded down to its largest thousand, million or billion: "R$ 1.234.567,89" is read
+  /// as "mais de um milhão de reais" and "R$ 999.999.999,99" as "quase um bilhão de reais".
+  #[default]
+  Approximate,
+  /// Read as "mais de" the threshold.
+  Cap,
+}
+
+/// How math symbols are read in a language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathWords {
+  pub percent: String,
+  pub plus: String,
+  pub minus: String,
+  pub equals: String,
+}
+
+impl MathWords {
+  pub fn portuguese() -> Self {
+    Self {
+      percent: String::from("por cento"),
+      plus: String::from("mais"),
+      minus: String::from("menos"),
+      equals: String::from("igual a"),
+    }
+  }
+}
+
+/// How the thousands and the decimal part of numbers are separated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberLocale {
+  /// "1.234,56", how numbers are written in Brazil.
+  #[default]
+  DecimalComma,
+  /// "1,234.56".
+  DecimalPoint,
+  /// Picks the locale from the voice that reads the message with `for_language`.
+  Auto,
+}
+
+impl NumberLocale {
+  /// Guesses how numbers are written from a language or voice like "pt-BR" or "en-US".
+  pub fn for_language(language: &str) -> Self {
+    let language = language.split(['-', '_']).next().unwrap_or_default();
+
+    match language.to_lowercase().as_str() {
+      "pt" | "es" | "fr" | "de" | "it" | "nl" | "ru" | "tr" | "id" => NumberLocale::DecimalComma,
+      _ => NumberLocale::DecimalPoint,
+    }
+  }
+
+  /// Returns the locale used for a message read by `voice`.
+  pub fn for_voice(self, voice: &str) -> Self {
+    match self {
+      NumberLocale::Auto => NumberLocale::for_language(voice),
+      locale => locale,
+    }
+  }
+
+  /// Returns the thousands separator and the decimal separator.
+  fn separators(&self) -> (char, char) {
+    match self {
+      // Only reached when the voice is not known, numbers are written like in Brazil then.
+      NumberLocale::DecimalComma | NumberLocale::Auto => ('.', ','),
+      NumberLocale::DecimalPoint => (',', '.'),
+    }
+  }
+}
+
+/// The order of the day and the month in dates like "25/12/2024".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateOrder {
+  /// "25/12/2024", how dates are written in Brazil.
+  #[default]
+  DayMonth,
+  /// "12/25/2024".
+  MonthDay,
+}
+
+/// The grammatical gender of the noun a number refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gender {
+  Masculine,
+  Feminine,
+}
+
+const UNITS: [&str; 20] = [
+  "zero",
+  "um",
+  "dois",
+  "três",
+  "quatro",
+  "cinco",
+  "seis",
+  "sete",
+  "oito",
+  "nove",
+  "dez",
+  "onze",
+  "doze",
+  "treze",
+  "quatorze",
+  "quinze",
+  "dezesseis",
+  "dezessete",
+  "dezoito",
+  "dezenove",
+];
+
+const TENS: [&str; 10] = [
+  "",
+  "",
+  "vinte",
+  "trinta",
+  "quarenta",
+  "cinquenta",
+  "sessenta",
+  "setenta",
+  "oitenta",
+  "noventa",
+];
+
+const HUNDREDS: [&str; 10] = [
+  "",
+  "cento",
+  "duzentos",
+  "trezentos",
+  "quatrocentos",
+  "quinhentos",
+  "seiscentos",
+  "setecentos",
+  "oitocentos",
+  "novecentos",
+];
+
+/// Names of the groups of three digits, from the thousands up, in singular and plural.
+const SCALES: [(&str, &str); 6] = [
+  ("mil", "mil"),
+  ("milhão", "milhões"),
+  ("bilhão", "bilhões"),
+  ("trilhão", "trilhões"),
+  ("quatrilhão", "quatrilhões"),
+  ("quintilhão", "quintilhões"),
+];
+
+const ORDINAL_UNITS: [&str; 10] = [
+  "", "primeiro", "segundo", "terceiro", "quarto", "quinto", "sexto", "sétimo", "oitavo", "nono",
+];
+
+const ORDINAL_TENS: [&str; 10] = [
+  "",
+  "décimo",
+  "vigésimo",
+  "trigésimo",
+  "quadragésimo",
+  "quinquagésimo",
+  "sexagésimo",
+  "septuagésimo",
+  "octogésimo",
+  "nonagésimo",
+];
+
+const ORDINAL_HUNDREDS: [&str; 10] = [
+  "",
+  "centésimo",
+  "ducentésimo",
+  "trecentésimo",
+  "quadringentésimo",
+  "quingentésimo",
+  "sexcentésimo",
+  "septingentésimo",
+  "octingentésimo",
+  "noningentésimo",
+];
+
+const MONTHS: [&str; 12] = [
+  "janeiro",
+  "fevereiro",
+  "março",
+  "abril",
+  "maio",
+  "junho",
+  "julho",
+  "agosto",
+  "setembro",
+  "outubro",
+  "novembro",
+  "dezembro",
+];
+
+/// The number of days of each month in a leap year.
+const DAYS_IN_MONTH: [u64; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// Changes the words that have a feminine form, "dois" becomes "duas" and "duzentos" becomes "duzentas".
+fn to_feminine(word: &str) -> String {
+  match word {
+    "um" => String::from("uma"),
+    "dois" => String::from("duas"),
+    word if word.ends_with("entos") => format!("{}as", word.trim_end_matches("os")),
+    word => word.to_string(),
+  }
+}
+
+/// Writes a number between 1 and 999.
+fn cardinal_below_thousand(n: u64, gender: Gender) -> String {
+  debug_assert!(n > 0 && n < 1000);
+
+  if n == 100 {
+    return String::from("cem");
+  }
+
+  let mut words = vec![];
+
+  let hundreds = n / 100;
+  let rest = n % 100;
+
+  if hundreds > 0 {
+    words.push(HUNDREDS[hundreds as usize].to_string());
+  }
+
+  if rest > 0 && rest < 20 {
+    words.push(UNITS[rest as usize].to_string());
+  } else if rest >= 20 {
+    words.push(TENS[(rest / 10) as usize].to_string());
+    if rest % 10 > 0 {
+      words.push(UNITS[(rest % 10) as usize].to_string());
+    }
+  }
+
+  if gender == Gender::Feminine {
+    words = words.iter().map(|word| to_feminine(word)).collect();
+  }
+
+  words.join(" e ")
+}
+
+/// Writes `n` in full, "1234" becomes "mil duzentos e trinta e quatro".
+pub fn cardinal(n: u64, gender: Gender) -> String {
+  if n == 0 {
+    return String::from("zero");
+  }
+
+  // Groups of three digits, starting from the units.
+  let mut groups = vec![];
+  let mut rest = n;
+  while rest > 0 {
+    groups.push(rest % 1000);
+    rest /= 1000;
+  }
+
+  let mut parts = vec![];
+
+  for (i, group) in groups.iter().enumerate().rev() {
+    if *group == 0 {
+      continue;
+    }
+
+    let part = match i {
+      0 => cardinal_below_thousand(*group, gender),
+      // "mil" instead of "um mil".
+      1 if *group == 1 => String::from("mil"),
+      // Thousands agree with the noun: "duas mil pessoas".
+      1 => format!("{} mil", cardinal_below_thousand(*group, gender)),
+      // Millions and above are masculine nouns: "dois milhões de pessoas".
+      i => {
+        let (singular, plural) = SCALES[i - 1];
+        format!(
+          "{} {}",
+          cardinal_below_thousand(*group, Gender::Masculine),
+          if *group == 1 { singular } else { plural }
+        )
+      }
+    };
+
+    parts.push((*group, part));
+  }
+
+  let mut text = String::new();
+
+  for (i, (group, part)) in parts.iter().enumerate() {
+    if i > 0 {
+      // The last group is joined with "e" when it is below one hundred or a round hundred:
+      // "mil e vinte", "mil e duzentos" but "mil duzentos e trinta".
+      let is_last = i == parts.len() - 1;
+      if is_last && (*group < 100 || group % 100 == 0) {
+        text.push_str(" e ");
+      } else {
+        text.push(' ');
+      }
+    }
+    text.push_str(part);
+  }
+
+  text
+}
+
+/// Writes the ordinal of `n`, "21" becomes "vigésimo primeiro".
+pub fn ordinal(n: u64, gender: Gender) -> String {
+  let mut words = vec![];
+
+  let thousands = n / 1000;
+  let rest = n % 1000;
+
+  if thousands > 0 {
+    words.push("milésimo");
+  }
+
+  for (value, names) in [
+    (rest / 100, ORDINAL_HUNDREDS),
+    ((rest % 100) / 10, ORDINAL_TENS),
+    (rest % 10, ORDINAL_UNITS),
+  ] {
+    if value > 0 {
+      words.push(names[value as usize]);
+    }
+  }
+
+  if words.is_empty() {
+    return String::from("zero");
+  }
+
+  let mut words: Vec<String> = words
+    .into_iter()
+    .map(|word| match gender {
+      Gender::Masculine => word.to_string(),
+      Gender::Feminine => format!("{}a", word.trim_end_matches('o')),
+    })
+    .collect();
+
+  // "2000º" is read as "dois milésimo".
+  if thousands > 1 {
+    words.insert(0, cardinal(thousands, Gender::Masculine));
+  }
+
+  words.join(" ")
+}
+
+/// Writes a fraction with a denominator that has a name, "3/4" becomes "três quartos".
+/// Returns `None` for denominators that would make the fraction awkward to read.
+pub fn fraction(numerator: u64, denominator: u64) -> Option<String> {
+  if numerator == 0 {
+    return None;
+  }
+
+  let (singular, plural) = match denominator {
+    2 => ("meio", "meios"),
+    3 => ("terço", "terços"),
+    4 => ("quarto", "quartos"),
+    5 => ("quinto", "quintos"),
+    6 => ("sexto", "sextos"),
+    7 => ("sétimo", "sétimos"),
+    8 => ("oitavo", "oitavos"),
+    9 => ("nono", "nonos"),
+    10 => ("décimo", "décimos"),
+    100 => ("centésimo", "centésimos"),
+    1000 => ("milésimo", "milésimos"),
+    _ => return None,
+  };
+
+  Some(match numerator {
+    1 if denominator == 2 => String::from("meio"),
+    1 => format!("um {}", singular),
+    n => format!("{} {}", cardinal(n, Gender::Masculine), plural),
+  })
+}
+
+/// Parses an amount written the brazilian way, "1.234,56", into reais and centavos.
+fn parse_brl(amount: &str) -> Option<(u64, u64)> {
+  let (integer, decimal) = match amount.split_once(',') {
+    None => (amount, ""),
+    Some((integer, decimal)) => (integer, decimal),
+  };
+
+  let is_number = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+
+  // Thousands may be separated by dots: "1.234".
+  let groups: Vec<&str> = integer.split('.').collect();
+  let valid_groups = match groups.len() {
+    1 => is_number(groups[0]),
+    _ => {
+      is_number(groups[0])
+        && groups[0].len() <= 3
+        && groups[1..]
+          .iter()
+          .all(|group| group.len() == 3 && is_number(group))
+    }
+  };
+  if !valid_groups {
+    return None;
+  }
+
+  let reais: u64 = groups.concat().parse().ok()?;
+
+  let centavos = match decimal.len() {
+    0 if !amount.contains(',') => 0,
+    1 | 2 if is_number(decimal) => {
+      // "10,5" is ten reais and fifty centavos.
+      let centavos: u64 = decimal.parse().ok()?;
+      if decimal.len() == 1 {
+        centavos * 10
+      } else {
+        centavos
+      }
+    }
+    _ => return None,
+  };
+
+  Some((reais, centavos))
+}
+
+/// Writes a whole amount of reais, `None` when it is zero.
+fn reais_text(reais: u64) -> Option<String> {
+  match reais {
+    0 => None,
+    1 => Some(String::from("um real")),
+    // "um milhão de reais".
+    reais if reais % 1_000_000 == 0 => {
+      Some(format!("{} de reais", cardinal(reais, Gender::Masculine)))
+    }
+    reais => Some(format!("{} reais", cardinal(reais, Gender::Masculine))),
+  }
+}
+
+/// Writes an amount above the threshold of `large_amounts` in its short form.
+fn large_amount(reais: u64, centavos: u64, large_amounts: LargeAmounts) -> Option<String> {
+  if reais <= large_amounts.threshold {
+    return None;
+  }
+
+  match large_amounts.style {
+    LargeAmountStyle::Cap => Some(format!(
+      "mais de {}",
+      reais_text(large_amounts.threshold).unwrap_or_else(|| String::from("zero reais"))
+    )),
+    LargeAmountStyle::Approximate => {
+      let mut scale = 1_000;
+      while reais / scale >= 1_000 && scale < 1_000_000_000_000_000 {
+        scale *= 1_000;
+      }
+
+      let leading = reais / scale;
+      let remainder = reais % scale;
+      if leading == 0 || (remainder == 0 && centavos == 0) {
+        return None;
+      }
+
+      // Close to the next round amount: 999.999 is almost a million.
+      Some(if remainder * 10 >= scale * 9 {
+        format!("quase {}", reais_text((leading + 1) * scale)?)
+      } else {
+        format!("mais de {}", reais_text(leading * scale)?)
+      })
+    }
+  }
+}
+
+/// Writes an amount of brazilian reais, "10,05" becomes "dez reais e cinco centavos".
+/// Amounts above the threshold of `large_amounts` are read in a short form.
+/// Returns `None` when `amount` is not a valid amount.
+pub fn currency(amount: &str, large_amounts: Option<LargeAmounts>) -> Option<String> {
+  let (reais, centavos) = parse_brl(amount)?;
+
+  if let Some(text) = large_amounts.and_then(|large| large_amount(reais, centavos, large)) {
+    return Some(text);
+  }
+
+  let reais_text = reais_text(reais);
+
+  let centavos_text = match centavos {
+    0 => None,
+    1 => Some(String::from("um centavo")),
+    centavos => Some(format!(
+      "{} centavos",
+      cardinal(centavos, Gender::Masculine)
+    )),
+  };
+
+  Some(match (reais_text, centavos_text) {
+    (None, None) => String::from("zero reais"),
+    (Some(reais), None) => reais,
+    (None, Some(centavos)) => centavos,
+    (Some(reais), Some(centavos)) => format!("{} e {}", reais, centavos),
+  })
+}
+
+/// Reads a number with separators, "1.234,56" with `NumberLocale::DecimalComma` is
+/// "mil duzentos e trinta e quatro vírgula cinquenta e seis". Returns `None` when `word`
+/// is not such a number, numbers without separators are read fine by the voice.
+pub fn decimal(word: &str, locale: NumberLocale) -> Option<String> {
+  let (thousands_separator, decimal_separator) = locale.separators();
+
+  if !word.contains([thousands_separator, decimal_separator]) {
+    return None;
+  }
+
+  let (integer, decimal) = match word.split_once(decimal_separator) {
+    None => (word, None),
+    Some((integer, decimal)) => (integer, Some(decimal)),
+  };
+
+  let is_number = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+
+  // Thousands are separated in groups of 3 digits: "1.234.567".
+  let groups: Vec<&str> = integer.split(thousands_separator).collect();
+  let valid_groups = is_number(groups[0])
+    && (groups.len() == 1
+      || (groups[0].len() <= 3
+        && groups[1..]
+          .iter()
+          .all(|group| group.len() == 3 && is_number(group))));
+  if !valid_groups {
+    return None;
+  }
+
+  let integer: u64 = groups.concat().parse().ok()?;
+  let integer_text = cardinal(integer, Gender::Masculine);
+
+  let decimal = match decimal {
+    None => return Some(integer_text),
+    Some(decimal) if is_number(decimal) && decimal.len() <= 6 => decimal,
+    Some(_) => return None,
+  };
+
+  // Leading zeros are read one by one: "1,05" is "um vírgula zero cinco".
+  let digits = decimal.trim_start_matches('0');
+  let mut words = vec!["zero"; decimal.len() - digits.len()];
+  let rest = match digits {
+    "" => None,
+    digits => Some(cardinal(digits.parse().ok()?, Gender::Masculine)),
+  };
+  words.extend(rest.as_deref());
+
+  Some(format!("{} vírgula {}", integer_text, words.join(" ")))
+}
+
+/// Replaces numbers with thousands or decimal separators in `text` by how they are read.
+pub fn verbalize_decimals(text: &str, locale: NumberLocale) -> String {
+  replace_words(text, |word| decimal(word, locale))
+}
+
+/// Replaces amounts like "R$ 10,05" and "R$10,05" in `text` by how they are read.
+pub fn verbalize_currency(text: &str, large_amounts: Option<LargeAmounts>) -> String {
+  let words: Vec<&str> = text.split_whitespace().collect();
+
+  let mut result = Vec::with_capacity(words.len());
+
+  let mut i = 0;
+  while i < words.len() {
+    let word = words[i];
+
+    // The amount may be separated from the currency symbol: "R$ 10,05".
+    let (amount, consumed) = match word.strip_prefix("R$") {
+      Some("") if i + 1 < words.len() => (words[i + 1], 2),
+      Some(amount) => (amount, 1),
+      None => (word, 0),
+    };
+
+    if consumed > 0 {
+      let (_, core, trailing) = split_punctuation(amount);
+      if let Some(verbalized) = currency(core, large_amounts) {
+        result.push(format!("{}{}", verbalized, trailing));
+        i += consumed;
+        continue;
+      }
+    }
+
+    result.push(word.to_string());
+    i += 1;
+  }
+
+  result.join(" ")
+}
+
+/// Splits `word` into the punctuation before it, the word itself and the punctuation after it.
+fn split_punctuation(word: &str) -> (&str, &str, &str) {
+  let is_punctuation = |character: char| character.is_ascii_punctuation() && character != '/';
+
+  let core = word.trim_start_matches(is_punctuation);
+  let leading = &word[..word.len() - core.len()];
+  let trimmed = core.trim_end_matches(is_punctuation);
+  let trailing = &core[trimmed.len()..];
+
+  (leading, trimmed, trailing)
+}
+
+/// Reads "1º" as "primeiro", "2ª" as "segunda".
+fn verbalize_ordinal(word: &str) -> Option<String> {
+  let (digits, gender) = if let Some(digits) = word.strip_suffix('º') {
+    (digits, Gender::Masculine)
+  } else if let Some(digits) = word.strip_suffix('ª') {
+    (digits, Gender::Feminine)
+  } else {
+    return None;
+  };
+
+  if digits.is_empty() || !digits.chars().all(|character| character.is_ascii_digit()) {
+    return None;
+  }
+
+  let n = digits.parse::<u64>().ok()?;
+  if n == 0 || n >= 1_000_000 {
+    return None;
+  }
+
+  Some(ordinal(n, gender))
+}
+
+/// Reads "1/2" as "meio", "3/4" as "três quartos". Only fractions smaller than one with
+/// halves, thirds and quarters are read, "5/10" and "3/5" are more likely scores.
+fn verbalize_fraction(word: &str) -> Option<String> {
+  let (numerator, denominator) = word.split_once('/')?;
+
+  let is_number =
+    |text: &str| !text.is_empty() && text.len() <= 4 && text.chars().all(|c| c.is_ascii_digit());
+  if !is_number(numerator) || !is_number(denominator) {
+    return None;
+  }
+
+  let numerator: u64 = numerator.parse().ok()?;
+  let denominator: u64 = denominator.parse().ok()?;
+  if !(2..=4).contains(&denominator) || numerator >= denominator {
+    return None;
+  }
+
+  fraction(numerator, denominator)
+}
+
+/// Reads "14:30" as "quatorze e trinta" and "14:00" as "quatorze horas".
+fn verbalize_time(word: &str) -> Option<String> {
+  let (hours, minutes) = word.split_once(':')?;
+
+  let is_number = |text: &str, max_len: usize| {
+    !text.is_empty() && text.len() <= max_len && text.chars().all(|c| c.is_ascii_digit())
+  };
+  if !is_number(hours, 2) || minutes.len() != 2 || !is_number(minutes, 2) {
+    return None;
+  }
+
+  let hours: u64 = hours.parse().ok()?;
+  let minutes: u64 = minutes.parse().ok()?;
+  if hours > 23 || minutes > 59 {
+    return None;
+  }
+
+  let hours_text = match hours {
+    0 => String::from("meia-noite"),
+    1 if minutes == 0 => String::from("uma hora"),
+    hours if minutes == 0 => format!("{} horas", cardinal(hours, Gender::Feminine)),
+    hours => cardinal(hours, Gender::Feminine),
+  };
+
+  if minutes == 0 {
+    return Some(hours_text);
+  }
+
+  Some(format!(
+    "{} e {}",
+    hours_text,
+    cardinal(minutes, Gender::Masculine)
+  ))
+}
+
+/// Reads a date like "25/12/2024" or "25/12" as "vinte e cinco de dezembro de dois mil
+/// e vinte e quatro". Years must have four digits. Without a year, the day and the month
+/// must have two digits and it's only a date when the day can't be a month, like
+/// "25/12", or when `after_dia` is set, like "dia 10/10". Otherwise "10/10" is more
+/// likely a score than the tenth of October.
+fn verbalize_date(word: &str, order: DateOrder, after_dia: bool) -> Option<String> {
+  let is_number = |text: &str, min_len: usize, max_len: usize| {
+    (min_len..=max_len).contains(&text.len()) && text.chars().all(|c| c.is_ascii_digit())
+  };
+
+  let parts: Vec<&str> = word.split('/').collect();
+  let (first, second, year) = match parts.as_slice() {
+    [first, second] if is_number(first, 2, 2) && is_number(second, 2, 2) => (*first, *second, None),
+    [first, second, year]
+      if is_number(first, 1, 2) && is_number(second, 1, 2) && is_number(year, 4, 4) =>
+    {
+      (*first, *second, Some(year.parse::<u64>().ok()?))
+    }
+    _ => return None,
+  };
+
+  let (day, month) = match order {
+    DateOrder::DayMonth => (first, second),
+    DateOrder::MonthDay => (second, first),
+  };
+
+  let day: u64 = day.parse().ok()?;
+  let month: usize = month.parse().ok()?;
+  if !(1..=12).contains(&month) || day == 0 || day > DAYS_IN_MONTH[month - 1] {
+    return None;
+  }
+
+  if year.is_none() && day <= 12 && !after_dia {
+    return None;
+  }
+
+  if let Some(year) = year {
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    if month == 2 && day == 29 && !leap_year {
+      return None;
+    }
+  }
+
+  let day = match day {
+    1 => String::from("primeiro"),
+    day => cardinal(day, Gender::Masculine),
+  };
+
+  let mut text = format!("{} de {}", day, MONTHS[month - 1]);
+
+  if let Some(year) = year {
+    text.push_str(" de ");
+    text.push_str(&cardinal(year, Gender::Masculine));
+  }
+
+  Some(text)
+}
+
+/// Calls `f` with each word in `text` without the punctuation around it and
+/// replaces the word by what `f` returns, if it returns something.
+pub fn replace_words<F>(text: &str, f: F) -> String
+where
+  F: Fn(&str) -> Option<String>,
+{
+  text
+    .split_whitespace()
+    .map(|word| {
+      let (leading, core, trailing) = split_punctuation(word);
+
+      match f(core) {
+        None => word.to_string(),
+        Some(replacement) => format!("{}{}{}", leading, replacement, trailing),
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Replaces ordinals and fractions in `text` by how they are read.
+pub fn verbalize_ordinals_and_fractions(text: &str) -> String {
+  replace_words(text, |word| {
+    verbalize_ordinal(word).or_else(|| verbalize_fraction(word))
+  })
+}
+
+/// Replaces dates in `text` by how they are read.
+pub fn verbalize_dates(text: &str, order: DateOrder) -> String {
+  let mut after_dia = false;
+
+  text
+    .split_whitespace()
+    .map(|word| {
+      let (leading, core, trailing) = split_punctuation(word);
+
+      let verbalized = match verbalize_date(core, order, after_dia) {
+        None => word.to_string(),
+        Some(date) => format!("{}{}{}", leading, date, trailing),
+      };
+
+      after_dia = leading.is_empty() && trailing.is_empty() && core.to_lowercase() == "dia";
+
+      verbalized
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Replaces times in `text` by how they are read.
+pub fn verbalize_times(text: &str) -> String {
+  replace_words(text, verbalize_time)
+}
+
+/// Handles numbers with more than `threshold` digits according to `policy`
+/// instead of having them read digit by digit.
+pub fn replace_long_numbers(text: &str, threshold: usize, policy: LongNumberPolicy) -> String {
+  replace_words(text, |word| {
+    // Digits may be separated: "1.234.567.890.123".
+    let digits = word.chars().filter(char::is_ascii_digit).count();
+    let is_long_number = digits > threshold
+      && word
+        .chars()
+        .all(|character| character.is_ascii_digit() || character == '.' || character == ',');
+    if !is_long_number {
+      return None;
+    }
+
+    Some(match policy {
+      LongNumberPolicy::Announce => String::from("número longo"),
+      LongNumberPolicy::Skip => String::new(),
+    })
+  })
+}
+
+/// Reads "50%" as "50 por cento", "2+2=4" as "2 mais 2 igual a 4" and "-5" as "menos 5".
+/// "+" and "=" are only read between numbers so "+55" is left alone. "-" is read at the
+/// start of a word right before a number, so "bem-vindo", "25-12-2024", "9999-1234" and
+/// "- 3 ovos" are left alone, or between numbers in a sentence with "=", so a score like
+/// "Brasil 2 - 1" is left alone.
+pub fn verbalize_math(text: &str, words: &MathWords) -> String {
+  let characters: Vec<char> = text.chars().collect();
+
+  // The closest character that isn't whitespace in the direction of `step`.
+  let neighbor = |i: usize, step: isize| {
+    let mut j = i as isize + step;
+    while j >= 0 && (j as usize) < characters.len() {
+      if !characters[j as usize].is_whitespace() {
+        return Some(characters[j as usize]);
+      }
+      j += step;
+    }
+    None
+  };
+
+  let is_digit = |character: Option<char>| character.is_some_and(|c| c.is_ascii_digit());
+
+  // Whether there's a "=" after `i` in the same sentence.
+  let equals_follows = |i: usize| {
+    characters[i..]
+      .iter()
+      .take_while(|character| !".!?\n".contains(**character))
+      .any(|character| *character == '=')
+  };
+
+  let mut buffer = String::with_capacity(text.len());
+
+  for (i, character) in characters.iter().enumerate() {
+    let word = match character {
+      '%' if i > 0 && characters[i - 1].is_ascii_digit() => Some(&words.percent),
+      '+' | '=' if is_digit(neighbor(i, -1)) && is_digit(neighbor(i, 1)) => Some(match character {
+        '+' => &words.plus,
+        _ => &words.equals,
+      }),
+      '-'
+        if (i == 0 || characters[i - 1].is_whitespace())
+          && (is_digit(characters.get(i + 1).copied())
+            || (is_digit(neighbor(i, -1)) && is_digit(neighbor(i, 1)) && equals_follows(i))) =>
+      {
+        Some(&words.minus)
+      }
+      _ => None,
+    };
+
+    match word {
+      None => buffer.push(*character),
+      Some(word) => {
+        buffer.push(' ');
+        buffer.push_str(word);
+        buffer.push(' ');
+      }
+    }
+  }
+
+  buffer.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cardinal() {
+    let tests = vec![
+      (0, "zero"),
+      (1, "um"),
+      (14, "quatorze"),
+      (21, "vinte e um"),
+      (100, "cem"),
+      (101, "cento e um"),
+      (110, "cento e dez"),
+      (999, "novecentos e noventa e nove"),
+      (1000, "mil"),
+      (1001, "mil e um"),
+      (1200, "mil e duzentos"),
+      (1234, "mil duzentos e trinta e quatro"),
+      (2024, "dois mil e vinte e quatro"),
+      (1_000_000, "um milhão"),
+      (2_000_001, "dois milhões e um"),
+      (
+        1_234_567,
+        "um milhão duzentos e trinta e quatro mil quinhentos e sessenta e sete",
+      ),
+      (3_000_000_000, "três bilhões"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        cardinal(input, Gender::Masculine),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_cardinal_feminine() {
+    let tests = vec![
+      (1, "uma"),
+      (2, "duas"),
+      (22, "vinte e duas"),
+      (200, "duzentas"),
+      (2_200, "duas mil e duzentas"),
+      (2_000_000, "dois milhões"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        cardinal(input, Gender::Feminine),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_ordinal() {
+    let tests = vec![
+      (1, Gender::Masculine, "primeiro"),
+      (2, Gender::Feminine, "segunda"),
+      (10, Gender::Masculine, "décimo"),
+      (21, Gender::Masculine, "vigésimo primeiro"),
+      (21, Gender::Feminine, "vigésima primeira"),
+      (100, Gender::Masculine, "centésimo"),
+      (1000, Gender::Feminine, "milésima"),
+      (4003, Gender::Feminine, "quatro milésima terceira"),
+    ];
+
+    for (n, gender, expected) in tests {
+      assert_eq!(expected, ordinal(n, gender), "n={} gender={:?}", n, gender);
+    }
+  }
+
+  #[test]
+  fn test_fraction() {
+    let tests = vec![
+      ((1, 2), Some("meio")),
+      ((3, 2), Some("três meios")),
+      ((1, 3), Some("um terço")),
+      ((2, 3), Some("dois terços")),
+      ((3, 4), Some("três quartos")),
+      ((1, 10), Some("um décimo")),
+      ((0, 2), None),
+      ((25, 12), None),
+    ];
+
+    for ((numerator, denominator), expected) in tests {
+      assert_eq!(
+        expected.map(String::from),
+        fraction(numerator, denominator),
+        "fraction={}/{}",
+        numerator,
+        denominator
+      );
+    }
+  }
+
+  #[test]
+  fn test_verbalize_ordinals_and_fractions() {
+    let tests = vec![
+      ("", ""),
+      ("olá", "olá"),
+      ("fiquei em 1º lugar", "fiquei em primeiro lugar"),
+      ("a 2ª vez", "a segunda vez"),
+      ("foi a 3ª.", "foi a terceira."),
+      ("comi 1/2 pizza", "comi meio pizza"),
+      ("faltam 3/4, quase lá", "faltam três quartos, quase lá"),
+      ("dia 25/12", "dia 25/12"),
+      ("e/ou", "e/ou"),
+      // Scores.
+      ("nota 5/10", "nota 5/10"),
+      ("10/10", "10/10"),
+      ("3/5 estrelas", "3/5 estrelas"),
+      ("ganhamos 3/2", "ganhamos 3/2"),
+      ("acertei 90/100", "acertei 90/100"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        verbalize_ordinals_and_fractions(input),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_verbalize_dates() {
+    let tests = vec![
+      ("", ""),
+      (
+        "natal é 25/12/2024!",
+        "natal é vinte e cinco de dezembro de dois mil e vinte e quatro!",
+      ),
+      (
+        "em 1/5/1999",
+        "em primeiro de maio de mil novecentos e noventa e nove",
+      ),
+      (
+        "até 05/03/2025.",
+        "até cinco de março de dois mil e vinte e cinco.",
+      ),
+      (
+        "29/02/2024",
+        "vinte e nove de fevereiro de dois mil e vinte e quatro",
+      ),
+      ("dia 25/12", "dia vinte e cinco de dezembro"),
+      ("natal é 25/12!", "natal é vinte e cinco de dezembro!"),
+      ("Dia 10/10", "Dia dez de outubro"),
+      ("dia 05/03.", "dia cinco de março."),
+      ("29/02", "vinte e nove de fevereiro"),
+      // Not dates.
+      ("comi 1/2 pizza", "comi 1/2 pizza"),
+      ("nota 10/10", "nota 10/10"),
+      ("05/03", "05/03"),
+      ("29/02/2023", "29/02/2023"),
+      ("29/02/1900", "29/02/1900"),
+      ("7/10", "7/10"),
+      ("31/04/2024", "31/04/2024"),
+      ("25/13", "25/13"),
+      ("25/12/24", "25/12/24"),
+      ("e/ou", "e/ou"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        verbalize_dates(input, DateOrder::DayMonth),
+        "input={}",
+        input
+      );
+    }
+
+    assert_eq!(
+      "vinte e cinco de dezembro de dois mil e vinte e quatro",
+      verbalize_dates("12/25/2024", DateOrder::MonthDay)
+    );
+    assert_eq!(
+      "vinte e nove de fevereiro de dois mil",
+      verbalize_dates("29/02/2000", DateOrder::DayMonth)
+    );
+  }
+
+  #[test]
+  fn test_verbalize_times() {
+    let tests = vec![
+      ("começa 14:30", "começa quatorze e trinta"),
+      ("às 14:00.", "às quatorze horas."),
+      ("1:00", "uma hora"),
+      ("02:05", "duas e cinco"),
+      ("00:15", "meia-noite e quinze"),
+      ("25:00", "25:00"),
+      ("14:3", "14:3"),
+      ("placar 3:2", "placar 3:2"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, verbalize_times(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn test_replace_long_numbers() {
+    let input = "pix 123456789012345, valeu! R$ 10";
+
+    assert_eq!(
+      "pix número longo, valeu! R$ 10",
+      replace_long_numbers(input, 12, LongNumberPolicy::Announce)
+    );
+    assert_eq!(
+      "pix , valeu! R$ 10",
+      replace_long_numbers(input, 12, LongNumberPolicy::Skip)
+    );
+    assert_eq!(
+      input,
+      replace_long_numbers(input, 15, LongNumberPolicy::Announce)
+    );
+    assert_eq!(
+      "pix número longo",
+      replace_long_numbers("pix 1.234.567.890.123", 12, LongNumberPolicy::Announce)
+    );
+  }
+
+  #[test]
+  fn test_currency() {
+    let tests = vec![
+      ("10", Some("dez reais")),
+      ("10,00", Some("dez reais")),
+      ("10,05", Some("dez reais e cinco centavos")),
+      ("10,5", Some("dez reais e cinquenta centavos")),
+      ("1,00", Some("um real")),
+      ("0,01", Some("um centavo")),
+      ("1,01", Some("um real e um centavo")),
+      ("0,00", Some("zero reais")),
+      (
+        "1.234,56",
+        Some("mil duzentos e trinta e quatro reais e cinquenta e seis centavos"),
+      ),
+      ("1.000.000,00", Some("um milhão de reais")),
+      ("1234", Some("mil duzentos e trinta e quatro reais")),
+      ("10,", None),
+      ("10,123", None),
+      ("1.23", None),
+      ("abc", None),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected.map(String::from),
+        currency(input, None),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn large_amounts_are_read_in_a_short_form() {
+    let approximate = LargeAmounts {
+      threshold: 1_000_000,
+      style: LargeAmountStyle::Approximate,
+    };
+    let cap = LargeAmounts {
+      threshold: 10_000,
+      style: LargeAmountStyle::Cap,
+    };
+
+    let tests = vec![
+      // Below the threshold.
+      (approximate, "999.999,99", "novecentos e noventa e nove mil novecentos e noventa e nove reais e noventa e nove centavos"),
+      (approximate, "1.000.000", "um milhão de reais"),
+      (cap, "10.000,50", "dez mil reais e cinquenta centavos"),
+      // Above the threshold.
+      (approximate, "999999999,99", "quase um bilhão de reais"),
+      (approximate, "1.234.567,89", "mais de um milhão de reais"),
+      (approximate, "25.000.000,01", "mais de vinte e cinco milhões de reais"),
+      (approximate, "2.000.000.000", "dois bilhões de reais"),
+      (cap, "999999999,99", "mais de dez mil reais"),
+    ];
+
+    for (large_amounts, input, expected) in tests {
+      assert_eq!(
+        Some(String::from(expected)),
+        currency(input, Some(large_amounts)),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_verbalize_currency() {
+    let tests = vec![
+      ("", ""),
+      ("doei R$ 10,05!", "doei dez reais e cinco centavos!"),
+      ("doei R$1,00, valeu", "doei um real, valeu"),
+      ("R$ abc", "R$ abc"),
+      ("R$", "R$"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, verbalize_currency(input, None), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn test_decimal() {
+    let tests = vec![
+      (
+        NumberLocale::DecimalComma,
+        "1.234,56",
+        Some("mil duzentos e trinta e quatro vírgula cinquenta e seis"),
+      ),
+      (NumberLocale::DecimalComma, "10.000", Some("dez mil")),
+      (
+        NumberLocale::DecimalComma,
+        "1,05",
+        Some("um vírgula zero cinco"),
+      ),
+      (
+        NumberLocale::DecimalComma,
+        "0,5",
+        Some("zero vírgula cinco"),
+      ),
+      (NumberLocale::DecimalComma, "1,234.56", None),
+      (NumberLocale::DecimalComma, "1.5", None),
+      (NumberLocale::DecimalComma, "1234", None),
+      (
+        NumberLocale::DecimalPoint,
+        "1,234.56",
+        Some("mil duzentos e trinta e quatro vírgula cinquenta e seis"),
+      ),
+      (
+        NumberLocale::DecimalPoint,
+        "3.14",
+        Some("três vírgula quatorze"),
+      ),
+      (NumberLocale::DecimalPoint, "1.234,56", None),
+    ];
+
+    for (locale, input, expected) in tests {
+      assert_eq!(
+        expected.map(String::from),
+        decimal(input, locale),
+        "locale={:?} input={}",
+        locale,
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_number_locale_for_language() {
+    let tests = vec![
+      ("pt-BR", NumberLocale::DecimalComma),
+      ("pt", NumberLocale::DecimalComma),
+      ("es-ES", NumberLocale::DecimalComma),
+      ("de_DE", NumberLocale::DecimalComma),
+      ("en-US", NumberLocale::DecimalPoint),
+      ("ja-JP", NumberLocale::DecimalPoint),
+      ("", NumberLocale::DecimalPoint),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        NumberLocale::for_language(input),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn auto_number_locale_follows_the_voice() {
+    assert_eq!(
+      NumberLocale::DecimalPoint,
+      NumberLocale::Auto.for_voice("en-US")
+    );
+    assert_eq!(
+      NumberLocale::DecimalComma,
+      NumberLocale::Auto.for_voice("pt-BR")
+    );
+    assert_eq!(
+      NumberLocale::DecimalComma,
+      NumberLocale::DecimalComma.for_voice("en-US")
+    );
+  }
+
+  #[test]
+  fn test_verbalize_decimals() {
+    let tests = vec![
+      (
+        NumberLocale::DecimalComma,
+        "doei 1.234,56, valeu",
+        "doei mil duzentos e trinta e quatro vírgula cinquenta e seis, valeu",
+      ),
+      (NumberLocale::DecimalComma, "1, 2 e 3", "1, 2 e 3"),
+      (
+        NumberLocale::DecimalPoint,
+        "sent 1,234.56!",
+        "sent mil duzentos e trinta e quatro vírgula cinquenta e seis!",
+      ),
+    ];
+
+    for (locale, input, expected) in tests {
+      assert_eq!(
+        expected,
+        verbalize_decimals(input, locale),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_verbalize_math() {
+    let words = MathWords::portuguese();
+
+    let tests = vec![
+      ("", ""),
+      ("50% desconto", "50 por cento desconto"),
+      ("50 % desconto", "50 % desconto"),
+      ("2+2", "2 mais 2"),
+      ("2 + 2 = 4", "2 mais 2 igual a 4"),
+      ("10 - 3 = 7", "10 menos 3 igual a 7"),
+      ("fez -5 graus", "fez menos 5 graus"),
+      ("bem-vindo", "bem-vindo"),
+      ("25-12-2024", "25-12-2024"),
+      ("ligue 9999-1234", "ligue 9999-1234"),
+      ("10-20 pessoas", "10-20 pessoas"),
+      ("bem - vindo", "bem - vindo"),
+      ("ligue +55 11", "ligue +55 11"),
+      ("a = b", "a = b"),
+      ("Brasil 2 - 1", "Brasil 2 - 1"),
+      (
+        "Brasil 2 - 1 hoje. 2 + 2 = 4",
+        "Brasil 2 - 1 hoje. 2 mais 2 igual a 4",
+      ),
+      ("- 3 ovos", "- 3 ovos"),
+      (
+        "receita:\n- 3 ovos\n- 2 xícaras",
+        "receita: - 3 ovos - 2 xícaras",
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, verbalize_math(input, &words), "input={}", input);
+    }
+  }
+}
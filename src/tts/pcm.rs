@@ -0,0 +1,254 @@
+//! Decodes mp3 files to PCM samples that can be fed directly to an audio device.
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+/// How the samples in a `PcmBuffer` should be played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+  pub sample_rate: u32,
+  /// The samples of the channels are interleaved: left, right, left, right...
+  pub channels: usize,
+}
+
+/// A bounded buffer of interleaved 16 bit samples written by the tts and read by the
+/// audio device. Writers wait while the buffer is full, the reader never waits so it
+/// can be called from the callback of an audio device.
+pub struct PcmBuffer {
+  capacity: usize,
+  samples: Mutex<VecDeque<i16>>,
+  format: Mutex<Option<PcmFormat>>,
+  /// Notified when samples are read and there's space in the buffer.
+  space: Notify,
+}
+
+impl PcmBuffer {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity: capacity.max(1),
+      samples: Mutex::new(VecDeque::with_capacity(capacity.max(1))),
+      format: Mutex::new(None),
+      space: Notify::new(),
+    }
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  /// How many samples are waiting to be read.
+  pub fn len(&self) -> usize {
+    self.samples.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// The format of the samples, known once the first samples are written.
+  pub fn format(&self) -> Option<PcmFormat> {
+    *self.format.lock().unwrap()
+  }
+
+  /// Moves up to `out.len()` samples to `out` and returns how many were moved.
+  pub fn read(&self, out: &mut [i16]) -> usize {
+    let read = {
+      let mut samples = self.samples.lock().unwrap();
+      let read = out.len().min(samples.len());
+      for (slot, sample) in out.iter_mut().zip(samples.drain(..read)) {
+        *slot = sample;
+      }
+      read
+    };
+
+    if read > 0 {
+      self.space.notify_one();
+    }
+
+    read
+  }
+
+  /// Writes every sample, waiting for the reader when the buffer is full.
+  async fn write(&self, mut samples: &[i16]) {
+    while !samples.is_empty() {
+      let space = self.space.notified();
+
+      {
+        let mut buffer = self.samples.lock().unwrap();
+        let written = samples.len().min(self.capacity - buffer.len());
+        buffer.extend(&samples[..written]);
+        samples = &samples[written..];
+      }
+
+      if !samples.is_empty() {
+        space.await;
+      }
+    }
+  }
+
+  /// Sets the format of the samples. Every sample in the buffer must have the same format.
+  fn set_format(&self, format: PcmFormat) -> Result<()> {
+    let mut current = self.format.lock().unwrap();
+
+    match *current {
+      None => {
+        *current = Some(format);
+        Ok(())
+      }
+      Some(current) if current == format => Ok(()),
+      Some(current) => Err(anyhow!(
+        "audio format changed in the middle of the message. expected={:?} got={:?}",
+        current,
+        format
+      )),
+    }
+  }
+}
+
+/// Decodes `mp3` in a blocking thread and writes its samples to `buffer`.
+pub async fn decode_into(mp3: Bytes, buffer: &PcmBuffer) -> Result<PcmFormat> {
+  let (format, samples) = tokio::task::spawn_blocking(move || decode(&mp3))
+    .await
+    .context("mp3 decoding task failed")??;
+
+  buffer.set_format(format)?;
+  buffer.write(&samples).await;
+
+  Ok(format)
+}
+
+/// Decodes every frame of `mp3`. Every frame must have the same format.
+fn decode(mp3: &[u8]) -> Result<(PcmFormat, Vec<i16>)> {
+  let mut decoder = minimp3::Decoder::new(std::io::Cursor::new(mp3));
+
+  let mut format = None;
+  let mut samples = Vec::new();
+
+  loop {
+    match decoder.next_frame() {
+      Ok(frame) => {
+        let frame_format = PcmFormat {
+          sample_rate: u32::try_from(frame.sample_rate)?,
+          channels: frame.channels.max(1),
+        };
+
+        match format {
+          None => format = Some(frame_format),
+          Some(format) if format == frame_format => {}
+          Some(format) => {
+            return Err(anyhow!(
+              "audio format changed in the middle of the file. expected={:?} got={:?}",
+              format,
+              frame_format
+            ))
+          }
+        }
+
+        samples.extend(frame.data);
+      }
+      Err(minimp3::Error::Eof) => break,
+      // Garbage between frames is skipped by the decoder.
+      Err(minimp3::Error::SkippedData) => continue,
+      Err(err) => return Err(anyhow!("unable to decode mp3 file. error={:?}", err)),
+    }
+  }
+
+  let format = format.ok_or_else(|| anyhow!("mp3 file has no audio"))?;
+
+  Ok((format, samples))
+}
+
+/// Writes `duration` of silence to `buffer`. Does nothing before the format is known.
+pub async fn write_silence(buffer: &PcmBuffer, duration: Duration) {
+  let format = match buffer.format() {
+    None => return,
+    Some(format) => format,
+  };
+
+  let frames = (duration.as_secs_f64() * f64::from(format.sample_rate)).round() as usize;
+
+  buffer.write(&vec![0; frames * format.channels]).await;
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use super::*;
+
+  const CLIP: &[u8] = include_bytes!("../../assets/henri.mp3");
+
+  fn decoded_samples(mp3: &[u8]) -> Vec<i16> {
+    let mut decoder = minimp3::Decoder::new(std::io::Cursor::new(mp3));
+    let mut samples = Vec::new();
+    while let Ok(frame) = decoder.next_frame() {
+      samples.extend(frame.data);
+    }
+    samples
+  }
+
+  #[tokio::test]
+  async fn writer_waits_while_the_buffer_is_full() -> Result<(), Box<dyn std::error::Error>> {
+    let buffer = Arc::new(PcmBuffer::new(4096));
+
+    let writer = tokio::spawn({
+      let buffer = Arc::clone(&buffer);
+      async move { decode_into(Bytes::from_static(CLIP), &buffer).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Nobody is reading, the writer is waiting for space.
+    assert_eq!(4096, buffer.len());
+    assert!(!writer.is_finished());
+
+    let mut samples = Vec::new();
+    let mut out = vec![0; 1000];
+    while !writer.is_finished() || !buffer.is_empty() {
+      let read = buffer.read(&mut out);
+      assert!(buffer.len() <= buffer.capacity());
+      samples.extend_from_slice(&out[..read]);
+      tokio::task::yield_now().await;
+    }
+
+    let format = writer.await??;
+    assert_eq!(Some(format), buffer.format());
+    assert!(format.sample_rate > 0);
+    assert!(format.channels > 0);
+
+    assert_eq!(decoded_samples(CLIP), samples);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn silence_has_the_format_of_the_audio() -> Result<(), Box<dyn std::error::Error>> {
+    let buffer = PcmBuffer::new(1_000_000);
+
+    // The format is not known yet.
+    write_silence(&buffer, Duration::from_secs(1)).await;
+    assert!(buffer.is_empty());
+
+    let format = decode_into(Bytes::from_static(CLIP), &buffer).await?;
+    let audio = buffer.len();
+
+    write_silence(&buffer, Duration::from_millis(500)).await;
+    assert_eq!(
+      format.sample_rate as usize / 2 * format.channels,
+      buffer.len() - audio
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn fails_without_audio() {
+    let buffer = PcmBuffer::new(10);
+    assert!(decode_into(Bytes::from_static(b"not an mp3 file"), &buffer)
+      .await
+      .is_err());
+  }
+}
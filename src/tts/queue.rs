@@ -0,0 +1,312 @@
+//! Limits how many messages are synthesized at the same time and decides
+//! which of the waiting messages goes next.
+
+use std::{
+  cmp::Ordering,
+  collections::BinaryHeap,
+  sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::sync::oneshot;
+
+use crate::contracts::tts::{TextToSpeech, TtsCapabilities};
+
+/// The priority of messages submitted through `TextToSpeech::create_audio`.
+pub const DEFAULT_PRIORITY: u32 = 0;
+
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+  /// How many messages are synthesized at the same time.
+  pub max_concurrency: usize,
+  /// A waiting message lets fewer than `aging_window` messages submitted after it
+  /// go ahead of it for each level of priority they have above it. Keeps low
+  /// priority messages from waiting forever when the queue is busy.
+  pub aging_window: u64,
+}
+
+impl Default for QueueConfig {
+  fn default() -> Self {
+    Self {
+      max_concurrency: 2,
+      aging_window: 10,
+    }
+  }
+}
+
+struct Entry {
+  /// Entries with a higher rank are synthesized first.
+  rank: i128,
+  /// The order the entry was submitted in. Breaks ties between ranks.
+  sequence: u64,
+  text: String,
+  sender: oneshot::Sender<Result<Vec<String>>>,
+}
+
+impl PartialEq for Entry {
+  fn eq(&self, other: &Self) -> bool {
+    self.rank == other.rank && self.sequence == other.sequence
+  }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Entry {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self
+      .rank
+      .cmp(&other.rank)
+      .then_with(|| other.sequence.cmp(&self.sequence))
+  }
+}
+
+struct State {
+  waiting: BinaryHeap<Entry>,
+  /// How many messages are being synthesized right now.
+  running: usize,
+  next_sequence: u64,
+}
+
+struct Inner {
+  tts: Arc<dyn TextToSpeech>,
+  config: QueueConfig,
+  state: Mutex<State>,
+}
+
+/// Wraps a `TextToSpeech` so at most `QueueConfig::max_concurrency` messages are
+/// synthesized at the same time. When messages are waiting, the ones with a higher
+/// priority, like big donations, are synthesized before chat messages.
+pub struct QueuedTts {
+  inner: Arc<Inner>,
+}
+
+impl QueuedTts {
+  pub fn new(tts: Arc<dyn TextToSpeech>, config: QueueConfig) -> Self {
+    Self {
+      inner: Arc::new(Inner {
+        tts,
+        config,
+        state: Mutex::new(State {
+          waiting: BinaryHeap::new(),
+          running: 0,
+          next_sequence: 0,
+        }),
+      }),
+    }
+  }
+
+  /// Waits for a turn and creates audio for `text`. Higher priorities go first.
+  #[tracing::instrument(skip_all, fields(priority = %priority))]
+  pub async fn submit(&self, text: String, priority: u32) -> Result<Vec<String>> {
+    let (sender, receiver) = oneshot::channel();
+
+    {
+      let mut state = self.inner.state.lock().unwrap();
+
+      let sequence = state.next_sequence;
+      state.next_sequence += 1;
+
+      // Every message submitted later has a lower rank than this one had, so
+      // waiting messages slowly catch up with higher priority messages.
+      let rank =
+        i128::from(priority) * i128::from(self.inner.config.aging_window) - i128::from(sequence);
+
+      state.waiting.push(Entry {
+        rank,
+        sequence,
+        text,
+        sender,
+      });
+    }
+
+    dispatch(&self.inner);
+
+    receiver
+      .await
+      .map_err(|_| anyhow!("message was dropped from the queue before being synthesized"))?
+  }
+}
+
+/// Starts synthesizing the waiting messages with the highest rank while there's capacity.
+fn dispatch(inner: &Arc<Inner>) {
+  let mut state = inner.state.lock().unwrap();
+
+  while state.running < inner.config.max_concurrency.max(1) {
+    let entry = match state.waiting.pop() {
+      None => break,
+      Some(entry) => entry,
+    };
+
+    // Nobody is waiting for the audio anymore.
+    if entry.sender.is_closed() {
+      continue;
+    }
+
+    state.running += 1;
+
+    let slot = RunningSlot {
+      inner: Arc::clone(inner),
+    };
+    tokio::spawn(async move {
+      let result = slot.inner.tts.create_audio(entry.text).await;
+
+      // The caller may have stopped waiting while the audio was being created.
+      let _ = entry.sender.send(result);
+    });
+  }
+}
+
+/// Frees the slot of a message being synthesized when dropped and lets the next
+/// message start, even when the synthesis panics.
+struct RunningSlot {
+  inner: Arc<Inner>,
+}
+
+impl Drop for RunningSlot {
+  fn drop(&mut self) {
+    self.inner.state.lock().unwrap().running -= 1;
+
+    dispatch(&self.inner);
+  }
+}
+
+#[async_trait]
+impl TextToSpeech for QueuedTts {
+  async fn create_audio(&self, text: String) -> Result<Vec<String>> {
+    self.submit(text, DEFAULT_PRIORITY).await
+  }
+
+  fn capabilities(&self) -> TtsCapabilities {
+    self.inner.tts.capabilities()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Records the order messages are synthesized in.
+  struct RecordingTts {
+    calls: Mutex<Vec<String>>,
+  }
+
+  #[async_trait]
+  impl TextToSpeech for RecordingTts {
+    async fn create_audio(&self, text: String) -> Result<Vec<String>> {
+      self.calls.lock().unwrap().push(text.clone());
+      tokio::task::yield_now().await;
+      Ok(vec![text])
+    }
+  }
+
+  /// Panics when asked to read "pânico".
+  struct PanickingTts;
+
+  #[async_trait]
+  impl TextToSpeech for PanickingTts {
+    async fn create_audio(&self, text: String) -> Result<Vec<String>> {
+      if text == "pânico" {
+        panic!("synthesis panicked");
+      }
+      Ok(vec![text])
+    }
+  }
+
+  async fn synthesis_order(aging_window: u64, messages: Vec<(&str, u32)>) -> Vec<String> {
+    let tts = Arc::new(RecordingTts {
+      calls: Mutex::new(Vec::new()),
+    });
+
+    let queue = QueuedTts::new(
+      Arc::clone(&tts) as Arc<dyn TextToSpeech>,
+      QueueConfig {
+        max_concurrency: 1,
+        aging_window,
+      },
+    );
+
+    // The first message takes the only slot, the others wait in the queue.
+    let results = futures::future::join_all(
+      messages
+        .into_iter()
+        .map(|(text, priority)| queue.submit(text.to_string(), priority)),
+    )
+    .await;
+
+    for result in results {
+      assert!(result.is_ok());
+    }
+
+    let calls = tts.calls.lock().unwrap().clone();
+    calls
+  }
+
+  #[tokio::test]
+  async fn synthesizes_higher_priority_messages_first() {
+    let order = synthesis_order(
+      10,
+      vec![
+        ("primeira", 0),
+        ("chat 1", 0),
+        ("doação 1", 10),
+        ("chat 2", 0),
+        ("doação 2", 10),
+      ],
+    )
+    .await;
+
+    assert_eq!(
+      vec!["primeira", "doação 1", "doação 2", "chat 1", "chat 2"],
+      order
+    );
+  }
+
+  #[tokio::test]
+  async fn low_priority_messages_are_not_starved() {
+    let order = synthesis_order(
+      2,
+      vec![
+        ("primeira", 0),
+        ("chat", 0),
+        ("doação 1", 1),
+        ("doação 2", 1),
+        ("doação 3", 1),
+      ],
+    )
+    .await;
+
+    assert_eq!(
+      vec!["primeira", "doação 1", "chat", "doação 2", "doação 3"],
+      order
+    );
+  }
+
+  #[tokio::test]
+  async fn a_panicking_synthesis_frees_its_slot() -> Result<(), Box<dyn std::error::Error>> {
+    let queue = QueuedTts::new(
+      Arc::new(PanickingTts),
+      QueueConfig {
+        max_concurrency: 1,
+        aging_window: 10,
+      },
+    );
+
+    assert!(queue.submit(String::from("pânico"), 0).await.is_err());
+
+    let urls = tokio::time::timeout(
+      std::time::Duration::from_secs(1),
+      queue.submit(String::from("olá"), 0),
+    )
+    .await??;
+    assert_eq!(vec![String::from("olá")], urls);
+
+    Ok(())
+  }
+}
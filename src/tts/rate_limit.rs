@@ -0,0 +1,233 @@
+//! Limits how many messages each sender can have read, with more room for the
+//! people who support the stream.
+
+use std::{
+  collections::{HashMap, VecDeque},
+  time::{Duration, Instant},
+};
+
+use tracing::info;
+
+use super::{queue, Tts, TtsError};
+
+/// How many messages a sender can have read in a period of time, so a single
+/// person can't flood the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderRateLimit {
+  /// The maximum number of messages read in any `window`.
+  pub max_messages: usize,
+  pub window: Duration,
+  /// Senders that are never limited, like the streamer and moderators.
+  pub exempt_senders: Vec<String>,
+}
+
+/// Who sent a message, people who support the stream get their messages read sooner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SenderRole {
+  #[default]
+  Regular,
+  Subscriber,
+  Vip,
+}
+
+/// How the messages of a `SenderRole` are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RolePolicy {
+  /// The priority of the messages in a `QueuedTts`.
+  pub priority: u32,
+  /// The sender can have this many times `SenderRateLimit::max_messages` messages read.
+  pub rate_limit_multiplier: usize,
+}
+
+impl Default for RolePolicy {
+  fn default() -> Self {
+    Self {
+      priority: queue::DEFAULT_PRIORITY,
+      rate_limit_multiplier: 1,
+    }
+  }
+}
+
+pub fn default_role_policies() -> HashMap<SenderRole, RolePolicy> {
+  HashMap::from([
+    (SenderRole::Regular, RolePolicy::default()),
+    (
+      SenderRole::Subscriber,
+      RolePolicy {
+        priority: 5,
+        rate_limit_multiplier: 2,
+      },
+    ),
+    (
+      SenderRole::Vip,
+      RolePolicy {
+        priority: 10,
+        rate_limit_multiplier: 4,
+      },
+    ),
+  ])
+}
+
+/// Senders that stopped sending messages are forgotten when there are at least this many.
+const MIN_SENDER_SWEEP: usize = 1024;
+
+/// When the recent messages of each sender were accepted.
+#[derive(Default)]
+pub(super) struct SenderWindows {
+  /// The oldest first.
+  accepted: HashMap<String, VecDeque<Instant>>,
+  /// Senders that stopped sending messages are forgotten when the map grows to this size.
+  sweep_at: usize,
+}
+
+impl Tts {
+  /// Checks `Config::sender_rate_limit` for a message from `sender_id` and returns the
+  /// priority the message should be submitted to a `QueuedTts` with.
+  pub fn admit_sender(&self, sender_id: &str, role: SenderRole) -> Result<u32, TtsError> {
+    self.check_sender_rate(sender_id, role, Instant::now())?;
+
+    Ok(self.role_policy(role).priority)
+  }
+
+  fn role_policy(&self, role: SenderRole) -> RolePolicy {
+    self
+      .config
+      .role_policies
+      .get(&role)
+      .copied()
+      .unwrap_or_default()
+  }
+
+  /// Records a message from `sender_id` at `now` unless the sender is over the limit.
+  pub(super) fn check_sender_rate(
+    &self,
+    sender_id: &str,
+    role: SenderRole,
+    now: Instant,
+  ) -> Result<(), TtsError> {
+    let limit = match &self.config.sender_rate_limit {
+      None => return Ok(()),
+      Some(limit) => limit,
+    };
+
+    if limit
+      .exempt_senders
+      .iter()
+      .any(|exempt| exempt == sender_id)
+    {
+      return Ok(());
+    }
+
+    let expired = |at: &Instant| now.saturating_duration_since(*at) >= limit.window;
+
+    let mut senders = self.senders.lock().unwrap();
+
+    // Forgets senders that have not sent anything recently so the map doesn't grow
+    // forever, without going through every sender on every message.
+    if senders.accepted.len() >= senders.sweep_at {
+      senders
+        .accepted
+        .retain(|_, accepted| !accepted.back().is_none_or(expired));
+      senders.sweep_at = (senders.accepted.len() * 2).max(MIN_SENDER_SWEEP);
+    }
+
+    let accepted = senders.accepted.entry(sender_id.to_string()).or_default();
+
+    while accepted.front().is_some_and(expired) {
+      accepted.pop_front();
+    }
+
+    let max_messages = limit
+      .max_messages
+      .saturating_mul(self.role_policy(role).rate_limit_multiplier);
+
+    if accepted.len() >= max_messages {
+      info!("sender rate limited. sender_id={}", sender_id);
+      return Err(TtsError::SenderRateLimited(sender_id.to_string()));
+    }
+
+    accepted.push_back(now);
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use crate::{contracts::http::MockHttpClient, tts::Config};
+
+  use super::*;
+
+  #[test]
+  fn idle_senders_are_forgotten() {
+    let tts = Tts::new(
+      Config {
+        sender_rate_limit: Some(SenderRateLimit {
+          max_messages: 1,
+          window: Duration::from_secs(60),
+          exempt_senders: Vec::new(),
+        }),
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    let start = Instant::now();
+
+    for i in 0..MIN_SENDER_SWEEP {
+      assert_eq!(
+        Ok(()),
+        tts.check_sender_rate(&format!("viewer{}", i), SenderRole::Regular, start)
+      );
+    }
+    assert_eq!(MIN_SENDER_SWEEP, tts.senders.lock().unwrap().accepted.len());
+
+    assert_eq!(
+      Ok(()),
+      tts.check_sender_rate("late", SenderRole::Regular, start + Duration::from_secs(60))
+    );
+    assert_eq!(1, tts.senders.lock().unwrap().accepted.len());
+  }
+
+  #[test]
+  fn sender_rate_limit_uses_a_sliding_window() {
+    let tts = Tts::new(
+      Config {
+        sender_rate_limit: Some(SenderRateLimit {
+          max_messages: 1,
+          window: Duration::from_secs(60),
+          exempt_senders: Vec::new(),
+        }),
+        ..Config::default()
+      },
+      Arc::new(MockHttpClient::new()),
+      None,
+    );
+
+    let start = Instant::now();
+
+    assert_eq!(
+      Ok(()),
+      tts.check_sender_rate("viewer", SenderRole::Regular, start)
+    );
+    assert_eq!(
+      Err(TtsError::SenderRateLimited(String::from("viewer"))),
+      tts.check_sender_rate(
+        "viewer",
+        SenderRole::Regular,
+        start + Duration::from_secs(59)
+      )
+    );
+    assert_eq!(
+      Ok(()),
+      tts.check_sender_rate(
+        "viewer",
+        SenderRole::Regular,
+        start + Duration::from_secs(60)
+      )
+    );
+  }
+}
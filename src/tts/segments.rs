@@ -0,0 +1,241 @@
+//! Splits a message into pieces read by different voices.
+//!
+//! A piece of the message can be read by another voice by wrapping it with
+//! `[voice=<name>]` and `[/voice]`, e.g. "olá [voice=en-US]hello[/voice]".
+//! `[pause=<milliseconds>]` adds silence after the text before it.
+//! Messages are written by viewers, so pauses are limited to a maximum.
+
+use std::time::Duration;
+
+const OPENING_TAG_START: &str = "[voice=";
+const CLOSING_TAG: &str = "[/voice]";
+const PAUSE_TAG_START: &str = "[pause=";
+
+/// A piece of a message and the voice that reads it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+  pub text: String,
+  /// `None` when the piece is read by the default voice.
+  pub voice: Option<String>,
+  /// Silence after the piece is read.
+  pub pause_after: Duration,
+}
+
+/// Returns the markup that adds a pause of `duration` to a message.
+pub fn pause_tag(duration: Duration) -> String {
+  format!("{}{}]", PAUSE_TAG_START, duration.as_millis())
+}
+
+/// Adds `text` to the segments and clears it.
+fn flush(segments: &mut Vec<Segment>, buffer: &mut String, voice: &Option<String>) {
+  let text = buffer.trim();
+  if !text.is_empty() {
+    match segments.last_mut() {
+      // Consecutive pieces read by the same voice are a single segment,
+      // unless there's a pause between them.
+      Some(last) if &last.voice == voice && last.pause_after.is_zero() => {
+        last.text.push(' ');
+        last.text.push_str(text);
+      }
+      _ => segments.push(Segment {
+        text: text.to_string(),
+        voice: voice.clone(),
+        pause_after: Duration::ZERO,
+      }),
+    }
+  }
+  buffer.clear();
+}
+
+/// Removes the voice and pause markup from `text`, for messages where viewers
+/// shouldn't control how they are read. Text that looks like markup but isn't is kept.
+pub fn strip_markup(text: &str) -> String {
+  split(text, Duration::ZERO)
+    .into_iter()
+    .map(|segment| segment.text)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Splits `text` into segments. Unbalanced tags don't fail: an opening tag without
+/// a closing tag lasts until the end of the message and closing tags without an
+/// opening tag are removed. Segments without text are not returned and
+/// consecutive segments read by the same voice are merged. Pauses before
+/// any text are ignored. The silence after each segment is at most `max_pause`.
+pub fn split(text: &str, max_pause: Duration) -> Vec<Segment> {
+  let mut segments = vec![];
+
+  let mut voice: Option<String> = None;
+  let mut buffer = String::new();
+  let mut rest = text;
+
+  while !rest.is_empty() {
+    if let Some(after_tag) = rest.strip_prefix(OPENING_TAG_START) {
+      if let Some(end) = after_tag.find(']') {
+        let name = after_tag[..end].trim();
+        if !name.is_empty() {
+          flush(&mut segments, &mut buffer, &voice);
+          voice = Some(name.to_string());
+          rest = &after_tag[end + 1..];
+          continue;
+        }
+      }
+    }
+
+    if let Some(after_tag) = rest.strip_prefix(PAUSE_TAG_START) {
+      if let Some(end) = after_tag.find(']') {
+        if let Ok(milliseconds) = after_tag[..end].trim().parse::<u64>() {
+          flush(&mut segments, &mut buffer, &voice);
+          if let Some(last) = segments.last_mut() {
+            last.pause_after = last
+              .pause_after
+              .saturating_add(Duration::from_millis(milliseconds))
+              .min(max_pause);
+          }
+          rest = &after_tag[end + 1..];
+          continue;
+        }
+      }
+    }
+
+    if let Some(after_tag) = rest.strip_prefix(CLOSING_TAG) {
+      flush(&mut segments, &mut buffer, &voice);
+      voice = None;
+      rest = after_tag;
+      // Keeps the words around the tag apart: "a[/voice]b".
+      buffer.push(' ');
+      continue;
+    }
+
+    // SAFETY: rest is not empty.
+    let character = rest.chars().next().unwrap();
+    buffer.push(character);
+    rest = &rest[character.len_utf8()..];
+  }
+
+  flush(&mut segments, &mut buffer, &voice);
+
+  segments
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn segment(text: &str, voice: Option<&str>) -> Segment {
+    Segment {
+      text: text.to_string(),
+      voice: voice.map(String::from),
+      pause_after: Duration::ZERO,
+    }
+  }
+
+  fn paused(text: &str, voice: Option<&str>, milliseconds: u64) -> Segment {
+    Segment {
+      pause_after: Duration::from_millis(milliseconds),
+      ..segment(text, voice)
+    }
+  }
+
+  #[test]
+  fn test_split() {
+    let tests = vec![
+      ("", vec![]),
+      ("olá pessoal", vec![segment("olá pessoal", None)]),
+      (
+        "olá [voice=en-US]hello everyone[/voice] tudo bem?",
+        vec![
+          segment("olá", None),
+          segment("hello everyone", Some("en-US")),
+          segment("tudo bem?", None),
+        ],
+      ),
+      (
+        "[voice=en-US]hello[/voice][voice=es-ES]hola[/voice]",
+        vec![
+          segment("hello", Some("en-US")),
+          segment("hola", Some("es-ES")),
+        ],
+      ),
+      // Opening tag without a closing tag.
+      (
+        "olá [voice=en-US]hello",
+        vec![segment("olá", None), segment("hello", Some("en-US"))],
+      ),
+      // Closing tag without an opening tag.
+      ("olá[/voice]pessoal", vec![segment("olá pessoal", None)]),
+      (
+        "a [voice=en-US]b[/voice][voice=en-US]c[/voice]",
+        vec![segment("a", None), segment("b c", Some("en-US"))],
+      ),
+      // Not a tag.
+      (
+        "[voice=]olá [voice",
+        vec![segment("[voice=]olá [voice", None)],
+      ),
+      ("[voice=en-US][/voice]", vec![]),
+      (
+        "então... [pause=600] ok",
+        vec![paused("então...", None, 600), segment("ok", None)],
+      ),
+      (
+        "[voice=en-US]so...[pause=400][pause=200] ok[/voice] tchau",
+        vec![
+          paused("so...", Some("en-US"), 600),
+          segment("ok", Some("en-US")),
+          segment("tchau", None),
+        ],
+      ),
+      // Pauses before any text and tags that are not pauses.
+      (
+        "[pause=300]olá [pause=x]",
+        vec![segment("olá [pause=x]", None)],
+      ),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        expected,
+        split(input, Duration::from_secs(3)),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn pauses_are_limited() {
+    let tests = vec![
+      ("a [pause=18446744073709551615] b", 3000),
+      ("a [pause=10800000] b", 3000),
+      ("a [pause=2000][pause=2000] b", 3000),
+      ("a [pause=1000] b", 1000),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(
+        vec![paused("a", None, expected), segment("b", None)],
+        split(input, Duration::from_secs(3)),
+        "input={}",
+        input
+      );
+    }
+  }
+
+  #[test]
+  fn test_strip_markup() {
+    let tests = vec![
+      ("", ""),
+      ("olá pessoal", "olá pessoal"),
+      (
+        "olá [voice=en-US]hello[/voice] tchau [pause=99999]",
+        "olá hello tchau",
+      ),
+      ("nota [10/10]", "nota [10/10]"),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, strip_markup(input), "input={}", input);
+    }
+  }
+}
@@ -0,0 +1,158 @@
+//! Aggregate numbers about the messages that were synthesized, used to plan capacity.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Upper bounds of the buckets of message lengths, in characters.
+const LENGTH_BOUNDS: &[u64] = &[10, 25, 50, 100, 200, 500];
+
+/// Upper bounds of the buckets of chunk counts.
+const CHUNK_BOUNDS: &[u64] = &[1, 2, 4, 8, 16];
+
+/// Counts how many values fall in each bucket. Updating it is a couple of atomic
+/// additions so it can be shared by every synthesis.
+#[derive(Debug)]
+struct Histogram {
+  /// Inclusive upper bound of each bucket. There's one more bucket, without a bound,
+  /// for the values above the last one.
+  bounds: &'static [u64],
+  counts: Vec<AtomicU64>,
+  sum: AtomicU64,
+}
+
+impl Histogram {
+  fn new(bounds: &'static [u64]) -> Self {
+    Self {
+      bounds,
+      counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+      sum: AtomicU64::new(0),
+    }
+  }
+
+  fn record(&self, value: u64) {
+    let bucket = self
+      .bounds
+      .iter()
+      .position(|bound| value <= *bound)
+      .unwrap_or(self.bounds.len());
+
+    self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    self.sum.fetch_add(value, Ordering::Relaxed);
+  }
+
+  fn snapshot(&self) -> HistogramSnapshot {
+    let buckets: Vec<Bucket> = self
+      .counts
+      .iter()
+      .enumerate()
+      .map(|(i, count)| Bucket {
+        le: self.bounds.get(i).copied(),
+        count: count.load(Ordering::Relaxed),
+      })
+      .collect();
+
+    HistogramSnapshot {
+      count: buckets.iter().map(|bucket| bucket.count).sum(),
+      sum: self.sum.load(Ordering::Relaxed),
+      buckets,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+  /// The largest value counted in the bucket, `None` for the last bucket.
+  pub le: Option<u64>,
+  pub count: u64,
+}
+
+/// The values recorded in a histogram when the snapshot was taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+  pub buckets: Vec<Bucket>,
+  /// How many values were recorded.
+  pub count: u64,
+  /// The sum of the values recorded.
+  pub sum: u64,
+}
+
+/// Lengths and chunk counts of the messages synthesized.
+#[derive(Debug)]
+pub struct Stats {
+  original_len: Histogram,
+  normalized_len: Histogram,
+  chunks: Histogram,
+}
+
+impl Default for Stats {
+  fn default() -> Self {
+    Self {
+      original_len: Histogram::new(LENGTH_BOUNDS),
+      normalized_len: Histogram::new(LENGTH_BOUNDS),
+      chunks: Histogram::new(CHUNK_BOUNDS),
+    }
+  }
+}
+
+impl Stats {
+  pub fn record(&self, original_len: usize, normalized_len: usize, chunks: usize) {
+    self.original_len.record(original_len as u64);
+    self.normalized_len.record(normalized_len as u64);
+    self.chunks.record(chunks as u64);
+  }
+
+  pub fn snapshot(&self) -> StatsSnapshot {
+    StatsSnapshot {
+      original_len: self.original_len.snapshot(),
+      normalized_len: self.normalized_len.snapshot(),
+      chunks: self.chunks.snapshot(),
+    }
+  }
+}
+
+/// Lengths and chunk counts of the messages synthesized since the `Tts` was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsSnapshot {
+  /// Length in characters of the messages as they were received.
+  pub original_len: HistogramSnapshot,
+  /// Length in characters of the messages after preprocessing.
+  pub normalized_len: HistogramSnapshot,
+  /// How many chunks each message was split in.
+  pub chunks: HistogramSnapshot,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn values_are_counted_in_the_first_bucket_that_fits_them() {
+    let histogram = Histogram::new(&[1, 2, 4]);
+
+    for value in [0, 1, 2, 3, 4, 5, 100] {
+      histogram.record(value);
+    }
+
+    assert_eq!(
+      HistogramSnapshot {
+        buckets: vec![
+          Bucket {
+            le: Some(1),
+            count: 2
+          },
+          Bucket {
+            le: Some(2),
+            count: 1
+          },
+          Bucket {
+            le: Some(4),
+            count: 2
+          },
+          Bucket { le: None, count: 2 },
+        ],
+        count: 7,
+        sum: 115,
+      },
+      histogram.snapshot()
+    );
+  }
+}
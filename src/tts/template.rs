@@ -0,0 +1,138 @@
+//! Extracts donations from messages wrapped in a template by the service that sent
+//! them, like "NewDonation|Fulano|10,00|valeu pela live".
+
+/// What a field of a `DonationTemplate` contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateField {
+  /// Text that must be in the field for the message to match the template, like "NewDonation".
+  Literal(String),
+  /// Who donated.
+  Donor,
+  /// How much was donated, written like "10,05".
+  Amount,
+  /// The message sent with the donation. The last field can contain the delimiter.
+  Message,
+  /// A field that is not used, like the id of the donation.
+  Ignored,
+}
+
+/// The layout of the messages sent by a donation service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DonationTemplate {
+  /// Separates the fields.
+  pub delimiter: char,
+  /// What each field contains, in order.
+  pub fields: Vec<TemplateField>,
+}
+
+/// A donation extracted from a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Donation {
+  pub donor: String,
+  pub amount: String,
+  /// Empty when the template has no message field.
+  pub message: String,
+}
+
+impl DonationTemplate {
+  /// Returns `None` when `text` does not match the template: it has fewer fields than
+  /// the template, a literal is different or the donor or amount is missing.
+  pub fn parse(&self, text: &str) -> Option<Donation> {
+    let values: Vec<&str> = text.splitn(self.fields.len(), self.delimiter).collect();
+    if values.len() != self.fields.len() {
+      return None;
+    }
+
+    let mut donor = None;
+    let mut amount = None;
+    let mut message = String::new();
+
+    for (field, value) in self.fields.iter().zip(values) {
+      let value = value.trim();
+
+      match field {
+        TemplateField::Literal(literal) if value != literal => return None,
+        TemplateField::Literal(_) | TemplateField::Ignored => {}
+        TemplateField::Donor => donor = Some(value),
+        TemplateField::Amount => amount = Some(value),
+        TemplateField::Message => message = value.to_string(),
+      }
+    }
+
+    match (donor, amount) {
+      (Some(donor), Some(amount)) if !donor.is_empty() && !amount.is_empty() => Some(Donation {
+        donor: donor.to_string(),
+        amount: amount.to_string(),
+        message,
+      }),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn donation(donor: &str, amount: &str, message: &str) -> Donation {
+    Donation {
+      donor: donor.to_string(),
+      amount: amount.to_string(),
+      message: message.to_string(),
+    }
+  }
+
+  #[test]
+  fn test_parse() {
+    let template = DonationTemplate {
+      delimiter: '|',
+      fields: vec![
+        TemplateField::Literal(String::from("NewDonation")),
+        TemplateField::Donor,
+        TemplateField::Amount,
+        TemplateField::Message,
+      ],
+    };
+
+    let tests = vec![
+      (
+        "NewDonation|Fulano|1000|valeu pela live",
+        Some(donation("Fulano", "1000", "valeu pela live")),
+      ),
+      (
+        "NewDonation| Fulano |10,05|a | b",
+        Some(donation("Fulano", "10,05", "a | b")),
+      ),
+      (
+        "NewDonation|Fulano|10,05|",
+        Some(donation("Fulano", "10,05", "")),
+      ),
+      ("NewSubscriber|Fulano|10,05|oi", None),
+      ("NewDonation|Fulano|10,05", None),
+      ("NewDonation||10,05|oi", None),
+      ("olá pessoal", None),
+      ("", None),
+    ];
+
+    for (input, expected) in tests {
+      assert_eq!(expected, template.parse(input), "input={}", input);
+    }
+  }
+
+  #[test]
+  fn fields_can_be_in_any_order() {
+    let template = DonationTemplate {
+      delimiter: ';',
+      fields: vec![
+        TemplateField::Ignored,
+        TemplateField::Amount,
+        TemplateField::Donor,
+      ],
+    };
+
+    assert_eq!(
+      Some(donation("Fulano", "5,00", "")),
+      template.parse("abc123;5,00;Fulano")
+    );
+  }
+}